@@ -0,0 +1,52 @@
+//! Frame times of the headless draw path, run with `cargo bench --bench draw`. Drawing goes
+//! to a `RecordingRenderer`, so this measures sampling, rule lookup and the batching sort,
+//! not what batching saves on the GPU.
+
+use std::time::{Duration, Instant};
+
+use dualgrid_raylib::renderer::RecordingRenderer;
+use dualgrid_raylib::tilemap::{TileMap, TileRules};
+use dualgrid_raylib::types::Rectangle;
+
+const SIZE: i32 = 256;
+const FRAMES: u32 = 20;
+
+/// A `SIZE` by `SIZE` chunk half filled with noise, drawn with the rules of the demo
+fn noise_map() -> TileMap {
+    let rules = TileRules::new()
+        .with_bytes_yaml_file(include_bytes!("../include/tile_rules.yaml"))
+        .and_then(TileRules::load_headless)
+        .unwrap();
+    let mut map = TileMap::new(rules).unwrap();
+    map.add_chunk(0, 0, SIZE, SIZE);
+    map.random_fill(0.5, 1);
+    map
+}
+
+/// Average time `draw_to` takes to draw the whole map, after a frame to warm up caches
+fn frame_time(map: &TileMap) -> Duration {
+    let size = map.scaled_tile_size();
+    let view = Rectangle::new(0.0, 0.0, SIZE as f32 * size.x, SIZE as f32 * size.y);
+    map.draw_to(&mut RecordingRenderer::new(), view);
+
+    let start = Instant::now();
+    for _ in 0..FRAMES {
+        let mut renderer = RecordingRenderer::new();
+        map.draw_to(&mut renderer, view);
+        std::hint::black_box(renderer.calls.len());
+    }
+    start.elapsed() / FRAMES
+}
+
+fn main() {
+    let mut map = noise_map();
+    println!("{0}x{0} chunk, {1} frames each", SIZE, FRAMES);
+
+    map.set_rule_cache(false);
+    println!("uncached rules: {:?}", frame_time(&map));
+    map.set_rule_cache(true);
+    println!("cached rules:   {:?}", frame_time(&map));
+
+    map.set_batching(true);
+    println!("batched:        {:?}", frame_time(&map));
+}
@@ -1,6 +1,6 @@
 use std::{env, fs, path::PathBuf};
 
-const COPY_DIR: &'static str = "resources";
+const COPY_DIR: &str = "resources";
 
 fn copy_dir(from: PathBuf, to: PathBuf) {
     if to.exists() {
@@ -36,5 +36,8 @@ fn main() {
 
     println!("cargo:rerun-if-changed=./{}/", COPY_DIR);
 
-    println!("cargo:rustc-link-lib=static=raylib");
+    // Headless builds don't link raylib at all
+    if env::var("CARGO_FEATURE_RENDER").is_ok() {
+        println!("cargo:rustc-link-lib=static=raylib");
+    }
 }
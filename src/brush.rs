@@ -0,0 +1,161 @@
+use crate::tilemap::TileMap;
+
+/// A block of tiles to stamp onto a map, as rows from the top
+#[derive(Clone, Debug, PartialEq)]
+pub struct Brush {
+    pub data: Vec<Vec<bool>>,
+}
+
+impl Brush {
+    pub fn new(data: Vec<Vec<bool>>) -> Self {
+        Self { data }
+    }
+
+    pub fn width(&self) -> usize {
+        self.data.iter().map(|row| row.len()).max().unwrap_or(0)
+    }
+
+    pub fn height(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Tile at the brush coordinate, rows shorter than the brush are empty past their end
+    fn get(&self, x: usize, y: usize) -> bool {
+        self.data
+            .get(y)
+            .and_then(|row| row.get(x))
+            .copied()
+            .unwrap_or(false)
+    }
+
+    /// Returns the brush rotated a quarter turn clockwise
+    pub fn rotated_90(&self) -> Self {
+        let (width, height) = (self.width(), self.height());
+        Self::new(
+            (0..width)
+                .map(|y| (0..height).map(|x| self.get(y, height - 1 - x)).collect())
+                .collect(),
+        )
+    }
+
+    /// Returns the brush mirrored left to right
+    pub fn flipped_h(&self) -> Self {
+        let width = self.width();
+        Self::new(
+            (0..self.height())
+                .map(|y| (0..width).map(|x| self.get(width - 1 - x, y)).collect())
+                .collect(),
+        )
+    }
+
+    /// Returns the brush mirrored top to bottom
+    pub fn flipped_v(&self) -> Self {
+        Self::new(self.data.iter().rev().cloned().collect())
+    }
+}
+
+/// Shape of the tiles `TileMap::paint` sets around its center
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BrushShape {
+    /// Every tile within the radius on both axes
+    Square,
+    /// Tiles whose distance to the center is at most the radius
+    #[default]
+    Circle,
+    /// Tiles whose Manhattan distance to the center is at most the radius
+    Diamond,
+}
+
+impl BrushShape {
+    /// Whether the tile `(dx, dy)` away from the center is painted with `radius`
+    pub fn contains(self, dx: i32, dy: i32, radius: i32) -> bool {
+        match self {
+            BrushShape::Square => dx.abs() <= radius && dy.abs() <= radius,
+            BrushShape::Circle => dx * dx + dy * dy <= radius * radius,
+            BrushShape::Diamond => dx.abs() + dy.abs() <= radius,
+        }
+    }
+}
+
+impl TileMap {
+    /// Writes the brush with its top left at `(x, y)`, see `set_region`
+    pub fn stamp(&mut self, x: i32, y: i32, brush: &Brush) {
+        self.set_region(x, y, &brush.data);
+    }
+
+    /// Sets the tiles of `shape` within `radius` tiles of `(x, y)` in a single batch. A
+    /// radius of 0 sets only `(x, y)` whatever the shape.
+    pub fn paint(&mut self, x: i32, y: i32, value: bool, radius: i32, shape: BrushShape) {
+        let radius = radius.max(0);
+        self.batch(|map| {
+            for dy in -radius..=radius {
+                for dx in -radius..=radius {
+                    if shape.contains(dx, dy, radius) {
+                        map.set(x + dx, y + dy, value);
+                    }
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tilemap::tests::map;
+
+    /// A brush from rows of `#` and `.`
+    fn brush(rows: &[&str]) -> Brush {
+        Brush::new(
+            rows.iter()
+                .map(|row| row.chars().map(|c| c == '#').collect())
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn brushes_rotate_and_flip() {
+        let l = brush(&["#.", "#.", "##"]);
+        assert_eq!((l.width(), l.height()), (2, 3));
+
+        assert_eq!(l.rotated_90(), brush(&["###", "#.."]));
+        assert_eq!(l.flipped_h(), brush(&[".#", ".#", "##"]));
+        assert_eq!(l.flipped_v(), brush(&["##", "#.", "#."]));
+        assert_eq!(l.rotated_90().rotated_90(), l.flipped_h().flipped_v());
+        assert_eq!(l.rotated_90().rotated_90().rotated_90().rotated_90(), l);
+    }
+
+    #[test]
+    fn stamp_writes_the_brush_at_its_top_left() {
+        let mut map = map(".....\n.....\n.....\n");
+        map.stamp(1, 0, &brush(&["#.", "#.", "##"]).rotated_90());
+        assert_eq!(map.to_ascii(), ".###.\n.#...\n.....\n");
+    }
+
+    #[test]
+    fn brush_shapes_paint_around_the_center() {
+        let painted = |shape| {
+            let mut canvas = map(".....\n.....\n.....\n.....\n.....\n");
+            canvas.paint(2, 2, true, 2, shape);
+            canvas.to_ascii()
+        };
+        assert_eq!(
+            painted(BrushShape::Square),
+            "#####\n#####\n#####\n#####\n#####\n"
+        );
+        assert_eq!(
+            painted(BrushShape::Circle),
+            "..#..\n.###.\n#####\n.###.\n..#..\n"
+        );
+        assert_eq!(
+            painted(BrushShape::Diamond),
+            "..#..\n.###.\n#####\n.###.\n..#..\n"
+        );
+
+        // Circle and diamond only part ways past a radius of 2
+        assert!(BrushShape::Circle.contains(2, 2, 3));
+        assert!(!BrushShape::Diamond.contains(2, 2, 3));
+        assert!(BrushShape::Square.contains(0, 0, 0));
+        assert!(!BrushShape::Square.contains(1, 0, 0));
+    }
+}
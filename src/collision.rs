@@ -0,0 +1,93 @@
+use std::collections::BTreeMap;
+
+use crate::tilemap::TileMap;
+use crate::types::Rectangle;
+
+impl TileMap {
+    /// Whether the tile is solid for collisions, the same as `get`
+    pub fn is_solid(&self, x: i32, y: i32) -> bool {
+        self.get(x, y)
+    }
+
+    /// Covers the solid tiles inside the chunks' bounding box with as few rectangles as a
+    /// greedy merge finds: runs of solid tiles on each row, then runs spanning the same
+    /// columns on consecutive rows merged into one. Rectangles are in world space at the
+    /// current scale, tile `(x, y)` covering `x * width` to `(x + 1) * width`.
+    pub fn collision_rects(&self) -> Vec<Rectangle> {
+        let Some((min_x, min_y, max_x, max_y)) = self.extent() else {
+            return vec![];
+        };
+
+        let size = self.scaled_tile_size();
+        let rect = |x0: i32, x1: i32, y0: i32, y1: i32| {
+            Rectangle::new(
+                x0 as f32 * size.x,
+                y0 as f32 * size.y,
+                (x1 - x0) as f32 * size.x,
+                (y1 - y0) as f32 * size.y,
+            )
+        };
+
+        let mut rects = vec![];
+        // Runs of the previous row as (start x, end x) to the row they started on
+        let mut open: BTreeMap<(i32, i32), i32> = BTreeMap::new();
+        for y in min_y..max_y {
+            let mut next: BTreeMap<(i32, i32), i32> = BTreeMap::new();
+            let mut x = min_x;
+            while x < max_x {
+                if !self.is_solid(x, y) {
+                    x += 1;
+                    continue;
+                }
+
+                let start = x;
+                while x < max_x && self.is_solid(x, y) {
+                    x += 1;
+                }
+                let start_y = open.remove(&(start, x)).unwrap_or(y);
+                next.insert((start, x), start_y);
+            }
+
+            // Runs that didn't continue on this row are done
+            for ((x0, x1), y0) in open {
+                rects.push(rect(x0, x1, y0, y));
+            }
+            open = next;
+        }
+        for ((x0, x1), y0) in open {
+            rects.push(rect(x0, x1, y0, max_y));
+        }
+        rects
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tilemap::tests::map;
+
+    #[test]
+    fn a_solid_block_is_one_rectangle() {
+        let block = map("....\n.###\n.###\n.###\n");
+        assert_eq!(
+            block.collision_rects(),
+            vec![Rectangle::new(32.0, 32.0, 96.0, 96.0)]
+        );
+        assert!(block.is_solid(1, 1) && !block.is_solid(0, 1));
+
+        // Rows of different widths can't merge
+        let mut l = map("#.\n##\n").with_scale(1.0);
+        let mut rects = l.collision_rects();
+        rects.sort_by(|a, b| a.y.total_cmp(&b.y));
+        assert_eq!(
+            rects,
+            vec![
+                Rectangle::new(0.0, 0.0, 8.0, 8.0),
+                Rectangle::new(0.0, 8.0, 16.0, 8.0)
+            ]
+        );
+
+        l.fill_all(false);
+        assert!(l.collision_rects().is_empty());
+    }
+}
@@ -0,0 +1,146 @@
+use std::fmt;
+
+use crate::types::Rectangle;
+
+#[derive(Debug)]
+pub enum TileError {
+    NotLoaded,
+    MissingAtlas,
+    MissingYaml,
+    ReadYaml(std::io::Error),
+    InvalidUtf8(std::str::Utf8Error),
+    ParseYaml(serde_yaml::Error),
+    ReadJson(std::io::Error),
+    ParseJson(serde_json::Error),
+    #[cfg(feature = "ron")]
+    ReadRon(std::io::Error),
+    #[cfg(feature = "ron")]
+    ParseRon(ron::error::SpannedError),
+    InvalidSize,
+    InvalidSprite,
+    InvalidNeighbors(usize, usize),
+    AtlasLoad(String),
+    TextureLoad(String),
+    Io(std::io::Error),
+    ParseMap(serde_yaml::Error),
+    #[cfg(feature = "bincode")]
+    ParseBin(bincode::Error),
+    UnsupportedVersion(u32),
+    InvalidMap,
+    InvalidAscii(char),
+    EmptyMap,
+    ExportImage(String),
+    InvalidCsv(String),
+    RaggedChunk,
+    SpriteOutOfBounds {
+        rule_index: usize,
+        rect: Rectangle,
+    },
+    DuplicateRule(usize, usize),
+    UnknownAtlas(String),
+    UnknownMaterial(String),
+    InFile(String, Box<TileError>), // An error reading the file at the path
+}
+
+impl fmt::Display for TileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TileError::NotLoaded => write!(f, "the tile rules were not loaded"),
+            TileError::MissingAtlas => write!(f, "no sprite atlas was provided"),
+            TileError::MissingYaml => write!(f, "no yaml or json file was provided"),
+            TileError::ReadYaml(e) => write!(f, "failed to read the yaml file: {}", e),
+            TileError::InvalidUtf8(e) => write!(f, "the rules file is not valid utf-8: {}", e),
+            TileError::ParseYaml(e) => write!(f, "failed to parse the yaml file: {}", e),
+            TileError::ReadJson(e) => write!(f, "failed to read the json file: {}", e),
+            TileError::ParseJson(e) => write!(f, "failed to parse the json file: {}", e),
+            #[cfg(feature = "ron")]
+            TileError::ReadRon(e) => write!(f, "failed to read the ron file: {}", e),
+            #[cfg(feature = "ron")]
+            TileError::ParseRon(e) => write!(f, "failed to parse the ron file: {}", e),
+            TileError::InvalidSize => write!(f, "invalid size value"),
+            TileError::InvalidSprite => write!(f, "invalid sprite value"),
+            TileError::InvalidNeighbors(found, expected) => write!(
+                f,
+                "a rule has {} neighbors, {} were expected",
+                found, expected
+            ),
+            TileError::AtlasLoad(e) => write!(f, "failed to load the sprite atlas image: {}", e),
+            TileError::TextureLoad(e) => write!(f, "failed to load the sprite texture: {}", e),
+            TileError::Io(e) => write!(f, "io error: {}", e),
+            TileError::ParseMap(e) => write!(f, "failed to parse the map file: {}", e),
+            #[cfg(feature = "bincode")]
+            TileError::ParseBin(e) => write!(f, "failed to encode or decode the binary map: {}", e),
+            TileError::UnsupportedVersion(v) => write!(f, "unsupported map file version {}", v),
+            TileError::InvalidMap => write!(f, "the map file chunk data doesn't match its size"),
+            TileError::InvalidAscii(c) => {
+                write!(f, "unexpected character {:?} in the ascii map", c)
+            }
+            TileError::EmptyMap => write!(f, "the map has no filled tiles"),
+            TileError::ExportImage(path) => write!(f, "failed to export the image to {}", path),
+            TileError::InvalidCsv(cell) => write!(f, "invalid value {:?} in the csv", cell),
+            TileError::RaggedChunk => write!(f, "the rows of the chunk data differ in length"),
+            TileError::SpriteOutOfBounds { rule_index, rect } => write!(
+                f,
+                "the sprite of rule {} at ({}, {}) sized {}x{} is outside of the atlas",
+                rule_index, rect.x, rect.y, rect.width, rect.height
+            ),
+            TileError::DuplicateRule(first, duplicate) => write!(
+                f,
+                "rule {} matches the same neighbors as rule {}",
+                duplicate, first
+            ),
+            TileError::UnknownAtlas(name) => write!(f, "no atlas is named {:?}", name),
+            TileError::UnknownMaterial(name) => write!(f, "no material is named {:?}", name),
+            TileError::InFile(path, e) => write!(f, "{}: {}", path, e),
+        }
+    }
+}
+
+impl TileError {
+    /// Wraps the error with the path of the file it came from, so the message names it
+    pub(crate) fn in_file(self, path: &str) -> TileError {
+        TileError::InFile(path.to_string(), Box::new(self))
+    }
+
+    /// Line and column of a rules or map file parse error, both starting at 1
+    pub fn location(&self) -> Option<(usize, usize)> {
+        match self {
+            TileError::ParseYaml(e) | TileError::ParseMap(e) => {
+                e.location().map(|l| (l.line(), l.column()))
+            }
+            TileError::ParseJson(e) => Some((e.line(), e.column())),
+            TileError::InFile(_, e) => e.location(),
+            #[cfg(feature = "ron")]
+            TileError::ParseRon(e) => Some((e.position.line, e.position.col)),
+            _ => None,
+        }
+    }
+}
+
+impl std::error::Error for TileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TileError::ReadYaml(e) => Some(e),
+            TileError::InvalidUtf8(e) => Some(e),
+            TileError::ParseYaml(e) => Some(e),
+            TileError::ReadJson(e) => Some(e),
+            TileError::ParseJson(e) => Some(e),
+            #[cfg(feature = "ron")]
+            TileError::ReadRon(e) => Some(e),
+            #[cfg(feature = "ron")]
+            TileError::ParseRon(e) => Some(e),
+            TileError::Io(e) => Some(e),
+            TileError::ParseMap(e) => Some(e),
+            TileError::InFile(_, e) => Some(e.as_ref()),
+            #[cfg(feature = "bincode")]
+            TileError::ParseBin(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<serde_yaml::Error> for TileError {
+    fn from(e: serde_yaml::Error) -> Self {
+        TileError::ParseYaml(e)
+    }
+}
@@ -0,0 +1,103 @@
+use std::collections::VecDeque;
+
+/// A single tile edit, from `old` to `new`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TileChange {
+    pub x: i32,
+    pub y: i32,
+    pub old: u8,
+    pub new: u8,
+}
+
+/// Undo and redo stacks of strokes, a stroke being the edits undone together
+pub struct History {
+    undo: VecDeque<Vec<TileChange>>,
+    redo: Vec<Vec<TileChange>>,
+    stroke: Option<Vec<TileChange>>, // Edits of the stroke in progress
+    depth: usize,
+}
+
+impl History {
+    /// Creates a history keeping at most `depth` strokes, dropping the oldest ones
+    pub fn new(depth: usize) -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: vec![],
+            stroke: None,
+            depth,
+        }
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty() || self.stroke.as_ref().is_some_and(|s| !s.is_empty())
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.stroke = None;
+    }
+
+    /// Groups every following edit into one stroke until `end_stroke`
+    pub fn begin_stroke(&mut self) {
+        self.stroke.get_or_insert_with(Vec::new);
+    }
+
+    pub fn end_stroke(&mut self) {
+        if let Some(stroke) = self.stroke.take() {
+            self.push_undo(stroke);
+        }
+    }
+
+    pub fn in_stroke(&self) -> bool {
+        self.stroke.is_some()
+    }
+
+    /// Adds an edit to the stroke in progress, or as a stroke of its own outside of one.
+    /// Any new edit drops the redo stack.
+    pub fn record(&mut self, change: TileChange) {
+        self.redo.clear();
+        match self.stroke {
+            Some(ref mut stroke) => stroke.push(change),
+            None => self.push_undo(vec![change]),
+        }
+    }
+
+    fn push_undo(&mut self, stroke: Vec<TileChange>) {
+        if stroke.is_empty() || self.depth == 0 {
+            return;
+        }
+
+        self.undo.push_back(stroke);
+        while self.undo.len() > self.depth {
+            self.undo.pop_front();
+        }
+    }
+
+    /// Takes the last stroke to undo, ending the one in progress first
+    pub(crate) fn pop_undo(&mut self) -> Option<Vec<TileChange>> {
+        self.end_stroke();
+        self.undo.pop_back()
+    }
+
+    pub(crate) fn pop_redo(&mut self) -> Option<Vec<TileChange>> {
+        self.redo.pop()
+    }
+
+    pub(crate) fn push_redo(&mut self, stroke: Vec<TileChange>) {
+        self.redo.push(stroke);
+    }
+
+    /// Puts a redone stroke back without dropping the rest of the redo stack
+    pub(crate) fn push_redone(&mut self, stroke: Vec<TileChange>) {
+        self.push_undo(stroke);
+    }
+}
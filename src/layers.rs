@@ -0,0 +1,117 @@
+#[cfg(feature = "render")]
+use raylib::prelude::{Camera2D, RaylibDraw, RaylibDrawHandle, RaylibMode2D};
+
+use crate::renderer::TileRenderer;
+use crate::tilemap::TileMap;
+use crate::types::Rectangle;
+
+/// A stack of tilemaps sharing the same coordinates, each with its own rules and atlas.
+/// The first layer is the bottom one.
+pub struct TileLayers {
+    pub layers: Vec<TileMap>,
+}
+
+impl TileLayers {
+    pub fn new() -> Self {
+        Self { layers: vec![] }
+    }
+
+    /// Puts `layer` on top of the others and returns its index
+    pub fn add_layer(&mut self, layer: TileMap) -> usize {
+        self.layers.push(layer);
+        self.layers.len() - 1
+    }
+
+    pub fn layer(&self, index: usize) -> Option<&TileMap> {
+        self.layers.get(index)
+    }
+
+    pub fn layer_mut(&mut self, index: usize) -> Option<&mut TileMap> {
+        self.layers.get_mut(index)
+    }
+
+    pub fn remove_layer(&mut self, index: usize) -> Option<TileMap> {
+        if index >= self.layers.len() {
+            return None;
+        }
+
+        Some(self.layers.remove(index))
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        for layer in self.layers.iter_mut() {
+            layer.update(dt);
+        }
+    }
+
+    /// Draws every layer back to front
+    #[cfg(feature = "render")]
+    pub fn draw(&self, d: &mut RaylibDrawHandle) {
+        for layer in self.layers.iter() {
+            layer.draw(d);
+        }
+    }
+
+    #[cfg(feature = "render")]
+    pub fn draw_with_camera(&self, d: &mut RaylibMode2D<RaylibDrawHandle>, camera: &Camera2D) {
+        for layer in self.layers.iter() {
+            layer.draw_with_camera(d, camera);
+        }
+    }
+
+    #[cfg(feature = "render")]
+    pub fn draw_region<D: RaylibDraw>(&self, d: &mut D, view: Rectangle) {
+        for layer in self.layers.iter() {
+            layer.draw_region(d, view);
+        }
+    }
+
+    /// Hands every layer's tiles overlapping `view` to `renderer`, back to front
+    pub fn draw_to<R: TileRenderer>(&self, renderer: &mut R, view: Rectangle) {
+        for layer in self.layers.iter() {
+            layer.draw_to(renderer, view);
+        }
+    }
+}
+
+impl Default for TileLayers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::{DrawCall, RecordingRenderer};
+    use crate::tilemap::tests::map;
+
+    #[test]
+    fn layers_draw_back_to_front() {
+        let ground = map("##\n##\n");
+        let decoration = map("#.\n..\n");
+
+        let mut layers = TileLayers::default();
+        assert_eq!(layers.add_layer(ground), 0);
+        assert_eq!(layers.add_layer(decoration), 1);
+
+        let view = Rectangle::new(0.0, 0.0, 640.0, 480.0);
+        let mut renderer = RecordingRenderer::new();
+        layers.draw_to(&mut renderer, view);
+
+        let mut expected = RecordingRenderer::new();
+        layers.layer(0).unwrap().draw_to(&mut expected, view);
+        let ground_calls = expected.calls.len();
+        layers.layer(1).unwrap().draw_to(&mut expected, view);
+        assert_eq!(renderer.calls, expected.calls);
+
+        // Both layers share coordinates, so the tile at (0, 0) is drawn at the same spot
+        let top_left = |call: &DrawCall| call.dest.x == 16.0 && call.dest.y == 16.0;
+        assert!(renderer.calls[..ground_calls].iter().any(top_left));
+        assert!(renderer.calls[ground_calls..].iter().any(top_left));
+
+        layers.layer_mut(1).unwrap().set(0, 0, false);
+        assert!(layers.remove_layer(1).is_some_and(|layer| !layer.get(0, 0)));
+        assert!(layers.remove_layer(1).is_none());
+    }
+}
@@ -0,0 +1,22 @@
+//! Dual grid tilemaps drawn with raylib.
+//!
+//! The crate only logs through the `log` facade and never sets up a logger itself, pick the
+//! level with whichever logger the application uses. Creating a chunk logs at `debug`,
+//! warnings about rule sets missing combinations at `warn`.
+
+pub mod brush;
+pub mod collision;
+pub mod error;
+pub mod history;
+pub mod layers;
+pub mod map_file;
+pub mod pathfinding;
+pub mod raycast;
+pub mod regions;
+pub mod renderer;
+mod rng;
+pub mod rules_file;
+#[cfg(feature = "tiled")]
+pub mod tiled;
+pub mod tilemap;
+pub mod types;
@@ -1,14 +1,16 @@
-pub mod tilemap;
-
+use dualgrid_raylib::tilemap;
 use log::{error, info};
 use raylib::prelude::*;
 
 const SCREEN_WIDTH: i32 = 800;
 const SCREEN_HEIGHT: i32 = 608; // 600 does not divide by 8
+const CAMERA_SPEED: f32 = 300.0; // Pixels per second
 
 fn main() {
-    // Init the pretty_env_logger
-    unsafe { std::env::set_var("RUST_APP_LOG", "info") };
+    // Init the pretty_env_logger, at info unless RUST_APP_LOG says otherwise
+    if std::env::var_os("RUST_APP_LOG").is_none() {
+        unsafe { std::env::set_var("RUST_APP_LOG", "info") };
+    }
     pretty_env_logger::init_custom_env("RUST_APP_LOG");
 
     // Initialize raylib staff
@@ -30,39 +32,113 @@ fn main() {
     info!("Texture \"water.png\" loaded");
 
     // Load the tilemap
-    let tile_rules = tilemap::TileRules::new()
+    let tile_rules = match tilemap::TileRules::new()
         .with_bytes_yaml_file(include_bytes!("../include/tile_rules.yaml"))
-        .with_sprite_atlas("resources/grass.png")
-        .load(&mut rl, &thread);
-    let mut tilemap = tilemap::TileMap::new(tile_rules);
+        .and_then(|rules| {
+            rules
+                .with_sprite_atlas("resources/grass.png")
+                .load(&mut rl, &thread)
+        }) {
+        Ok(tile_rules) => tile_rules,
+        Err(e) => {
+            error!("Failed to load the tile rules: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let mut tilemap = match tilemap::TileMap::new(tile_rules) {
+        Ok(tilemap) => tilemap,
+        Err(e) => {
+            error!("Failed to create the tilemap: {}", e);
+            std::process::exit(1);
+        }
+    };
+    tilemap.enable_history(100);
 
     tilemap.add_chunk(
         0,
         0,
-        f32::ceil(SCREEN_WIDTH as f32 / 8.0 / 4.0) as i32,
-        f32::ceil(SCREEN_HEIGHT as f32 / 8.0 / 4.0) as i32,
+        f32::ceil(SCREEN_WIDTH as f32 / tilemap.scaled_tile_size().x) as i32,
+        f32::ceil(SCREEN_HEIGHT as f32 / tilemap.scaled_tile_size().y) as i32,
     );
 
+    let mut camera = Camera2D {
+        offset: Vector2::new(0.0, 0.0),
+        target: Vector2::new(0.0, 0.0),
+        rotation: 0.0,
+        zoom: 1.0,
+    };
+
+    let mut last_tile: Option<(i32, i32)> = None;
+
     // Enter the game loop
     while !rl.window_should_close() {
-        let mouse_pos = &rl.get_mouse_position();
+        // Pan the camera with WASD and zoom it with the mouse wheel
+        let speed = CAMERA_SPEED * rl.get_frame_time() / camera.zoom;
+        if rl.is_key_down(KeyboardKey::KEY_W) {
+            camera.target.y -= speed;
+        }
+        if rl.is_key_down(KeyboardKey::KEY_S) {
+            camera.target.y += speed;
+        }
+        if rl.is_key_down(KeyboardKey::KEY_A) {
+            camera.target.x -= speed;
+        }
+        if rl.is_key_down(KeyboardKey::KEY_D) {
+            camera.target.x += speed;
+        }
+        camera.zoom = f32::clamp(camera.zoom + rl.get_mouse_wheel_move() * 0.1, 0.25, 4.0);
+
+        tilemap.update(rl.get_frame_time());
+
+        // Reload the tile rules and atlas from disk
+        if rl.is_key_pressed(KeyboardKey::KEY_F5) {
+            match tilemap.reload_rules(&mut rl, &thread) {
+                Ok(()) => info!("Tile rules reloaded"),
+                Err(e) => error!("Failed to reload the tile rules: {}", e),
+            }
+        }
+
+        // Undo and redo whole strokes with Z and Y
+        if rl.is_key_pressed(KeyboardKey::KEY_Z) {
+            tilemap.undo();
+        }
+        if rl.is_key_pressed(KeyboardKey::KEY_Y) {
+            tilemap.redo();
+        }
+
+        let mouse_pos = rl.get_screen_to_world2D(rl.get_mouse_position(), camera);
+        let (tile_x, tile_y) = tilemap.screen_to_tile(mouse_pos);
 
         let mut d = rl.begin_drawing(&thread);
         d.clear_background(Color::WHITE);
 
-        // If the mouse is pressed, add a tile to the tilemap
+        // Everything painted while a button is held is undone at once
+        if d.is_mouse_button_pressed(MouseButton::MOUSE_BUTTON_LEFT)
+            || d.is_mouse_button_pressed(MouseButton::MOUSE_BUTTON_RIGHT)
+        {
+            tilemap.begin_stroke();
+        }
+        if d.is_mouse_button_released(MouseButton::MOUSE_BUTTON_LEFT)
+            || d.is_mouse_button_released(MouseButton::MOUSE_BUTTON_RIGHT)
+        {
+            tilemap.end_stroke();
+        }
+
+        // If the mouse is pressed, add a tile to the tilemap. Painting a line from the last
+        // tile keeps fast strokes from skipping tiles between frames.
+        let (last_x, last_y) = last_tile.unwrap_or((tile_x, tile_y));
         if d.is_mouse_button_down(MouseButton::MOUSE_BUTTON_LEFT) {
-            tilemap.set(
-                f32::floor(mouse_pos.x / 8.0 / 4.0) as i32,
-                f32::floor(mouse_pos.y / 8.0 / 4.0) as i32,
-                true,
-            );
+            tilemap.set_line(last_x, last_y, tile_x, tile_y, true);
+            last_tile = Some((tile_x, tile_y));
         } else if d.is_mouse_button_down(MouseButton::MOUSE_BUTTON_RIGHT) {
-            tilemap.set(
-                f32::floor(mouse_pos.x / 8.0 / 4.0) as i32,
-                f32::floor(mouse_pos.y / 8.0 / 4.0) as i32,
-                false,
-            );
+            tilemap.set_line(last_x, last_y, tile_x, tile_y, false);
+            last_tile = Some((tile_x, tile_y));
+        } else {
+            last_tile = None;
+        }
+
+        if d.is_mouse_button_pressed(MouseButton::MOUSE_BUTTON_MIDDLE) {
+            tilemap.toggle(tile_x, tile_y);
         }
 
         // Draw the water texture as the background
@@ -77,15 +153,14 @@ fn main() {
             }
         }
 
+        let mut d = d.begin_mode2D(camera);
+
         // Draw the tilemap
-        tilemap.draw(&mut d);
+        tilemap.draw_with_camera(&mut d, &camera);
 
         // Draw a squeare at the mouse position
-        d.draw_rectangle(
-            f32::floor(mouse_pos.x / 8.0 / 4.0) as i32 * 8 * 4,
-            f32::floor(mouse_pos.y / 8.0 / 4.0) as i32 * 8 * 4,
-            8 * 4,
-            8 * 4,
+        d.draw_rectangle_rec(
+            tilemap.tile_rect(tile_x, tile_y),
             Color::new(255, 0, 0, 128),
         );
     }
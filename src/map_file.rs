@@ -0,0 +1,507 @@
+#[cfg(feature = "render")]
+use raylib::prelude::Image;
+use serde::{Deserialize, Serialize};
+
+use crate::error::TileError;
+use crate::tilemap::{Chunk, TileMap, TileRules};
+
+/// Bumped whenever the layout of `MapFile` changes
+const MAP_FILE_VERSION: u32 = 1;
+
+// Yaml:
+// version: 1
+// chunks:
+//   - x: 0
+//     y: 0
+//     size_x: 25
+//     size_y: 19
+//     runs: [[0, 120], [1, 4], [0, 351]] # [tile, count]
+
+/// Tile data of a map without its rules, so it can be stored however the user likes
+/// and attached to a `TileRules` again with `TileMap::from_data`
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TileMapData {
+    pub chunks: Vec<ChunkData>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ChunkData {
+    pub x: i32,
+    pub y: i32,
+    pub size_x: i32,
+    pub size_y: i32,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub z: i32,
+    pub data: Vec<u8>, // Same layout as Chunk::data
+}
+
+#[derive(Serialize, Deserialize)]
+struct MapFile {
+    version: u32,
+    chunks: Vec<ChunkRecord>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ChunkRecord {
+    x: i32,
+    y: i32,
+    size_x: i32,
+    size_y: i32,
+    #[serde(default)]
+    z: i32,
+    runs: Vec<(u8, u32)>,
+}
+
+fn encode_runs(data: &[u8]) -> Vec<(u8, u32)> {
+    let mut runs: Vec<(u8, u32)> = vec![];
+    for &tile in data {
+        match runs.last_mut() {
+            Some((last, count)) if *last == tile => *count += 1,
+            _ => runs.push((tile, 1)),
+        }
+    }
+    runs
+}
+
+/// Number of tiles of a `size_x` by `size_y` chunk, `None` if the size is negative or
+/// too large for the `i32` coordinates of `Chunk`
+fn chunk_len(size_x: i32, size_y: i32) -> Option<usize> {
+    if size_x < 0 || size_y < 0 {
+        return None;
+    }
+    size_x.checked_mul(size_y).map(|len| len as usize)
+}
+
+/// Expands the runs of a chunk holding `len` tiles. The runs are summed before anything is
+/// allocated, so a file can't ask for more memory than its chunk sizes do.
+fn decode_runs(runs: &[(u8, u32)], len: usize) -> Result<Vec<u8>, TileError> {
+    let total: u64 = runs.iter().map(|&(_, count)| count as u64).sum();
+    if total != len as u64 {
+        return Err(TileError::InvalidMap);
+    }
+
+    let mut data = Vec::with_capacity(len);
+    for &(tile, count) in runs {
+        data.extend(std::iter::repeat_n(tile, count as usize));
+    }
+    Ok(data)
+}
+
+impl TileMap {
+    pub fn to_data(&self) -> TileMapData {
+        TileMapData {
+            chunks: self
+                .chunks
+                .iter()
+                .map(|chunk| ChunkData {
+                    x: chunk.x,
+                    y: chunk.y,
+                    size_x: chunk.size_x,
+                    size_y: chunk.size_y,
+                    z: chunk.z,
+                    data: chunk.data.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    pub fn from_data(data: TileMapData, rules: TileRules) -> Result<TileMap, TileError> {
+        let mut map = TileMap::new(rules)?;
+        for chunk in data.chunks {
+            if chunk_len(chunk.size_x, chunk.size_y) != Some(chunk.data.len()) {
+                return Err(TileError::InvalidMap);
+            }
+
+            let mut new_chunk =
+                Chunk::from_flat(chunk.x, chunk.y, chunk.size_x, chunk.size_y, chunk.data);
+            new_chunk.z = chunk.z;
+            map.insert_chunk(new_chunk);
+        }
+
+        Ok(map)
+    }
+
+    /// Writes the chunks of the map to `path`, the rules aren't saved
+    pub fn save(&self, path: &str) -> Result<(), TileError> {
+        let yaml = serde_yaml::to_string(&self.to_map_file()).map_err(TileError::ParseMap)?;
+        std::fs::write(path, yaml).map_err(TileError::Io)
+    }
+
+    /// Writes the map like `save` but as bincode, many times smaller and faster to read
+    /// for large worlds. Read it back with `load_bin`.
+    #[cfg(feature = "bincode")]
+    pub fn save_bin(&self, path: &str) -> Result<(), TileError> {
+        let bytes = bincode::serialize(&self.to_map_file()).map_err(TileError::ParseBin)?;
+        std::fs::write(path, bytes).map_err(TileError::Io)
+    }
+
+    fn to_map_file(&self) -> MapFile {
+        MapFile {
+            version: MAP_FILE_VERSION,
+            chunks: self
+                .to_data()
+                .chunks
+                .into_iter()
+                .map(|chunk| ChunkRecord {
+                    x: chunk.x,
+                    y: chunk.y,
+                    size_x: chunk.size_x,
+                    size_y: chunk.size_y,
+                    z: chunk.z,
+                    runs: encode_runs(&chunk.data),
+                })
+                .collect(),
+        }
+    }
+
+    /// Writes the map as text, `#` for filled tiles and `.` for empty ones, a line per row.
+    /// Every chunk is flattened into the bounding box of all chunks (see `extent`), so
+    /// gaps between chunks come out as `.` and the output always starts at its top left.
+    pub fn to_ascii(&self) -> String {
+        let Some((min_x, min_y, max_x, max_y)) = self.extent() else {
+            return String::new();
+        };
+
+        let mut text = String::with_capacity(((max_x - min_x + 1) * (max_y - min_y)) as usize);
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let filled = self
+                    .get_chunk_at(x, y)
+                    .is_some_and(|chunk| chunk.get(x - chunk.x, y - chunk.y));
+                text.push(if filled { '#' } else { '.' });
+            }
+            text.push('\n');
+        }
+        text
+    }
+
+    /// Builds a map holding a single chunk at the origin from text written like `to_ascii`'s.
+    /// Shorter lines are padded with empty tiles.
+    pub fn from_ascii(text: &str, rules: TileRules) -> Result<TileMap, TileError> {
+        let rows = text
+            .lines()
+            .map(|line| {
+                line.chars()
+                    .map(|c| match c {
+                        '#' => Ok(1),
+                        '.' => Ok(0),
+                        c => Err(TileError::InvalidAscii(c)),
+                    })
+                    .collect()
+            })
+            .collect::<Result<Vec<Vec<u8>>, TileError>>()?;
+        TileMap::from_rows(&rows, rules)
+    }
+
+    /// Writes the material id of every tile as comma separated values, a line per row, for
+    /// spreadsheets and scripts. Plain maps come out as `0` and `1`. Every chunk is flattened
+    /// into the active bounds (see `active_bounds`), so empty borders are left out and the
+    /// output starts at the top left filled tile.
+    pub fn to_csv(&self) -> String {
+        let Some((min_x, min_y, max_x, max_y)) = self.active_bounds() else {
+            return String::new();
+        };
+
+        let mut text = String::new();
+        for y in min_y..max_y {
+            let row: Vec<String> = (min_x..max_x)
+                .map(|x| {
+                    self.get_chunk_at(x, y)
+                        .map_or(0, |chunk| chunk.get_tile(x - chunk.x, y - chunk.y))
+                        .to_string()
+                })
+                .collect();
+            text.push_str(&row.join(","));
+            text.push('\n');
+        }
+        text
+    }
+
+    /// Builds a map holding a single chunk at the origin from values written like `to_csv`'s.
+    /// Shorter rows are padded with empty tiles.
+    pub fn from_csv(csv: &str, rules: TileRules) -> Result<TileMap, TileError> {
+        let mut rows: Vec<Vec<u8>> = vec![];
+        for line in csv.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            let row = line
+                .split(',')
+                .map(|cell| {
+                    let cell = cell.trim();
+                    cell.parse()
+                        .map_err(|_| TileError::InvalidCsv(cell.to_string()))
+                })
+                .collect::<Result<Vec<u8>, TileError>>()?;
+            rows.push(row);
+        }
+
+        TileMap::from_rows(&rows, rules)
+    }
+
+    /// Builds a map holding a single chunk at the origin from rows of tiles, padding the
+    /// shorter ones with empty tiles. Without any tile the map has no chunks.
+    pub(crate) fn from_rows(rows: &[Vec<u8>], rules: TileRules) -> Result<TileMap, TileError> {
+        let size_x = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+        let mut data = Vec::with_capacity(size_x * rows.len());
+        for row in rows.iter() {
+            data.extend_from_slice(row);
+            data.resize(data.len() + size_x - row.len(), 0);
+        }
+
+        let mut map = TileMap::new(rules)?;
+        if !data.is_empty() {
+            map.insert_chunk(Chunk::from_flat(
+                0,
+                0,
+                size_x as i32,
+                rows.len() as i32,
+                data,
+            ));
+        }
+        Ok(map)
+    }
+
+    /// Builds a map holding a single chunk at the origin the size of `image`, a pixel per
+    /// tile, filling the tiles whose pixels are brighter than `threshold`. Brightness is the
+    /// luma of the pixel scaled by its alpha, so transparent pixels are always empty. Any
+    /// uncompressed pixel format works, it's converted to 8-bit colors first.
+    #[cfg(feature = "render")]
+    pub fn from_image(
+        image: &Image,
+        threshold: u8,
+        rules: TileRules,
+    ) -> Result<TileMap, TileError> {
+        let (size_x, size_y) = (image.width(), image.height());
+        let data = image
+            .get_image_data()
+            .iter()
+            .map(|color| {
+                let luma =
+                    (color.r as u32 * 299 + color.g as u32 * 587 + color.b as u32 * 114) / 1000;
+                (luma * color.a as u32 / 255 > threshold as u32) as u8
+            })
+            .collect::<Vec<u8>>();
+
+        let mut map = TileMap::new(rules)?;
+        if size_x > 0 && size_y > 0 && data.len() == (size_x * size_y) as usize {
+            map.insert_chunk(Chunk::from_flat(0, 0, size_x, size_y, data));
+        }
+        Ok(map)
+    }
+
+    /// Reads a map written by `save` and draws it with `rules`
+    pub fn load_map(path: &str, rules: TileRules) -> Result<TileMap, TileError> {
+        let yaml = std::fs::read_to_string(path).map_err(TileError::Io)?;
+        let file: MapFile =
+            serde_yaml::from_str(&yaml).map_err(|e| TileError::ParseMap(e).in_file(path))?;
+        TileMap::from_map_file(file, rules)
+    }
+
+    /// Reads a map written by `save_bin` and draws it with `rules`
+    #[cfg(feature = "bincode")]
+    pub fn load_bin(path: &str, rules: TileRules) -> Result<TileMap, TileError> {
+        let bytes = std::fs::read(path).map_err(TileError::Io)?;
+        let file: MapFile =
+            bincode::deserialize(&bytes).map_err(|e| TileError::ParseBin(e).in_file(path))?;
+        TileMap::from_map_file(file, rules)
+    }
+
+    fn from_map_file(file: MapFile, rules: TileRules) -> Result<TileMap, TileError> {
+        if file.version != MAP_FILE_VERSION {
+            return Err(TileError::UnsupportedVersion(file.version));
+        }
+
+        let mut chunks = Vec::with_capacity(file.chunks.len());
+        for record in file.chunks {
+            let len = chunk_len(record.size_x, record.size_y).ok_or(TileError::InvalidMap)?;
+            chunks.push(ChunkData {
+                x: record.x,
+                y: record.y,
+                size_x: record.size_x,
+                size_y: record.size_y,
+                z: record.z,
+                data: decode_runs(&record.runs, len)?,
+            });
+        }
+        let data = TileMapData { chunks };
+
+        TileMap::from_data(data, rules)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tilemap::tests::{map, rules};
+
+    /// A path in the temp dir no other test writes to
+    fn temp_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("dualgrid-{}-{}", std::process::id(), name));
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn saved_maps_load_back() {
+        let mut saved = map("##..\n.#..\n....\n");
+        saved.add_chunk(-8, 4, 8, 8);
+        saved.set(-8, 4, true);
+        saved.set(-1, 11, true);
+        saved.set_chunk_z(-8, 4, 2);
+
+        let path = temp_path("saved.yaml");
+        saved.save(&path).unwrap();
+        let loaded = TileMap::load_map(&path, rules()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.to_data(), saved.to_data());
+        for ((x, y), filled) in saved.iter_tiles() {
+            assert_eq!(loaded.get(x, y), filled);
+        }
+    }
+
+    #[test]
+    fn runs_have_to_add_up_to_the_chunk() {
+        assert_eq!(encode_runs(&[0, 0, 1, 0]), vec![(0, 2), (1, 1), (0, 1)]);
+        assert_eq!(
+            decode_runs(&[(0, 2), (1, 1), (0, 1)], 4).unwrap(),
+            vec![0, 0, 1, 0]
+        );
+        assert!(decode_runs(&[(0, 2), (1, u32::MAX)], 4).is_err());
+        assert!(decode_runs(&[(0, 3)], 4).is_err());
+
+        let data = TileMapData {
+            chunks: vec![ChunkData {
+                x: 0,
+                y: 0,
+                size_x: i32::MAX,
+                size_y: 2,
+                z: 0,
+                data: vec![],
+            }],
+        };
+        assert!(matches!(
+            TileMap::from_data(data, rules()),
+            Err(TileError::InvalidMap)
+        ));
+    }
+
+    #[test]
+    fn ascii_round_trips() {
+        let text = "#..#\n.##.\n....\n#...\n";
+        assert_eq!(map(text).to_ascii(), text);
+
+        // Shorter lines are padded and gaps between chunks come out empty
+        let mut padded = map("#\n.#\n");
+        assert_eq!(padded.to_ascii(), "#.\n.#\n");
+        padded.add_chunk(3, 0, 1, 1);
+        padded.set(3, 0, true);
+        assert_eq!(padded.to_ascii(), "#..#\n.#..\n");
+
+        assert!(matches!(
+            TileMap::from_ascii("#x\n", rules()),
+            Err(TileError::InvalidAscii('x'))
+        ));
+        assert_eq!(
+            TileMap::from_ascii("", rules()).unwrap().chunks().count(),
+            0
+        );
+    }
+
+    #[cfg(feature = "bincode")]
+    #[test]
+    fn binary_maps_load_back() {
+        let mut saved = map("##..\n.#..\n");
+        saved.add_chunk(-4, -4, 4, 4);
+        saved.set(-1, -1, true);
+
+        let path = temp_path("saved.bin");
+        saved.save_bin(&path).unwrap();
+        let loaded = TileMap::load_bin(&path, rules()).unwrap();
+
+        // A YAML map isn't bincode
+        saved.save(&path).unwrap();
+        let yaml = TileMap::load_bin(&path, rules());
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.to_data(), saved.to_data());
+        assert!(yaml.is_err());
+    }
+
+    #[cfg(all(feature = "bincode", feature = "serde"))]
+    #[test]
+    fn binary_maps_are_smaller_than_json() {
+        let mut cave = TileMap::new(rules()).unwrap();
+        cave.add_chunk(0, 0, 128, 128);
+        cave.fill_rect(8, 8, 20, 30, true);
+        cave.fill_rect(40, 4, 12, 100, true);
+
+        let path = temp_path("cave.bin");
+        cave.save_bin(&path).unwrap();
+        let bin = std::fs::metadata(&path).unwrap().len() as usize;
+        std::fs::remove_file(&path).unwrap();
+        let json = serde_json::to_string(&cave.to_data()).unwrap().len();
+
+        assert!(
+            bin * 10 < json,
+            "{} bytes of bincode, {} of json",
+            bin,
+            json
+        );
+    }
+
+    #[test]
+    fn csv_round_trips() {
+        let csv = "1,0,2\n0,3,0\n";
+        let loaded = TileMap::from_csv(csv, rules()).unwrap();
+        assert_eq!(loaded.get_tile(2, 0), 2);
+        assert_eq!(loaded.to_csv(), csv);
+
+        // Empty borders are left out, short rows are padded
+        let bordered = map("....\n.#..\n..#.\n");
+        assert_eq!(bordered.to_csv(), "1,0\n0,1\n");
+        let padded = TileMap::from_csv(" 1 \n0, 1\n\n", rules()).unwrap();
+        assert_eq!(padded.to_csv(), "1,0\n0,1\n");
+        assert_eq!(map("..\n").to_csv(), "");
+
+        assert!(matches!(
+            TileMap::from_csv("1,x\n", rules()),
+            Err(TileError::InvalidCsv(cell)) if cell == "x"
+        ));
+        assert!(matches!(
+            TileMap::from_csv("256\n", rules()),
+            Err(TileError::InvalidCsv(_))
+        ));
+    }
+
+    #[cfg(feature = "render")]
+    #[test]
+    fn images_fill_their_bright_pixels() {
+        use raylib::prelude::{Color, PixelFormat};
+
+        let mut image = Image::gen_image_color(4, 2, Color::BLACK);
+        image.draw_pixel(0, 0, Color::WHITE);
+        image.draw_pixel(3, 1, Color::WHITE);
+        image.draw_pixel(1, 0, Color::new(50, 50, 50, 255));
+        image.draw_pixel(2, 1, Color::new(255, 255, 255, 0));
+        let expected = "#...\n...#\n";
+        let painted = TileMap::from_image(&image, 128, rules()).unwrap();
+        assert_eq!(painted.to_ascii(), expected);
+        assert!(painted.get(0, 0) && !painted.get(1, 0) && !painted.get(2, 1));
+
+        // Other pixel formats are converted to 8-bit colors first
+        let mut gray = image.clone();
+        gray.set_format(PixelFormat::PIXELFORMAT_UNCOMPRESSED_GRAY_ALPHA);
+        assert_eq!(
+            TileMap::from_image(&gray, 128, rules()).unwrap().to_ascii(),
+            expected
+        );
+        let mut float = image.clone();
+        float.set_format(PixelFormat::PIXELFORMAT_UNCOMPRESSED_R32G32B32A32);
+        assert_eq!(
+            TileMap::from_image(&float, 128, rules())
+                .unwrap()
+                .to_ascii(),
+            expected
+        );
+    }
+}
@@ -0,0 +1,138 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+use crate::tilemap::TileMap;
+
+impl TileMap {
+    /// Shortest path between two tiles over 4-connected empty tiles, see `find_path_with`
+    pub fn find_path(&self, start: (i32, i32), goal: (i32, i32)) -> Option<Vec<(i32, i32)>> {
+        self.find_path_with(start, goal, false)
+    }
+
+    /// Finds the shortest path from `start` to `goal` with A*, filled tiles being walls.
+    /// With `diagonal` it also steps diagonally, but never squeezes between two walls
+    /// touching at a corner. Returns every tile on the way, both ends included, or `None`
+    /// if the goal can't be reached.
+    ///
+    /// The search never leaves the bounding box of the chunks grown by a tile (and
+    /// stretched to hold both ends), so an unreachable goal doesn't search forever.
+    pub fn find_path_with(
+        &self,
+        start: (i32, i32),
+        goal: (i32, i32),
+        diagonal: bool,
+    ) -> Option<Vec<(i32, i32)>> {
+        if self.get(start.0, start.1) || self.get(goal.0, goal.1) {
+            return None;
+        }
+
+        let (min_x, min_y, max_x, max_y) = self
+            .extent()
+            .unwrap_or((start.0, start.1, start.0, start.1));
+        let min_x = i32::min(min_x, i32::min(start.0, goal.0)) - 1;
+        let min_y = i32::min(min_y, i32::min(start.1, goal.1)) - 1;
+        let max_x = i32::max(max_x, i32::max(start.0, goal.0) + 1);
+        let max_y = i32::max(max_y, i32::max(start.1, goal.1) + 1);
+        let walkable = |x: i32, y: i32| {
+            x >= min_x && x <= max_x && y >= min_y && y <= max_y && !self.get(x, y)
+        };
+
+        let heuristic = |(x, y): (i32, i32)| {
+            let (dx, dy) = ((x - goal.0).abs(), (y - goal.1).abs());
+            if diagonal {
+                i32::max(dx, dy)
+            } else {
+                dx + dy
+            }
+        };
+
+        let mut open = BinaryHeap::new();
+        let mut came_from: HashMap<(i32, i32), (i32, i32)> = HashMap::new();
+        let mut cost: HashMap<(i32, i32), i32> = HashMap::new();
+        cost.insert(start, 0);
+        open.push(Reverse((heuristic(start), start)));
+
+        while let Some(Reverse((_, current))) = open.pop() {
+            if current == goal {
+                let mut path = vec![current];
+                let mut tile = current;
+                while let Some(&previous) = came_from.get(&tile) {
+                    path.push(previous);
+                    tile = previous;
+                }
+                path.reverse();
+                return Some(path);
+            }
+
+            let (x, y) = current;
+            let mut steps = vec![(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)];
+            if diagonal {
+                for (dx, dy) in [(-1, -1), (1, -1), (-1, 1), (1, 1)] {
+                    // Both tiles beside the corner have to be open
+                    if walkable(x + dx, y) && walkable(x, y + dy) {
+                        steps.push((x + dx, y + dy));
+                    }
+                }
+            }
+
+            let current_cost = cost[&current];
+            for next in steps {
+                if !walkable(next.0, next.1) {
+                    continue;
+                }
+
+                let next_cost = current_cost + 1;
+                if cost.get(&next).is_some_and(|&known| known <= next_cost) {
+                    continue;
+                }
+
+                cost.insert(next, next_cost);
+                came_from.insert(next, current);
+                open.push(Reverse((next_cost + heuristic(next), next)));
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tilemap::tests::map;
+    use crate::tilemap::EdgeMode;
+
+    #[test]
+    fn paths_find_their_way_through_a_maze() {
+        let mut maze = map(".#...\n.#.#.\n...#.\n####.\n");
+        maze.set_edge_mode(EdgeMode::Filled);
+
+        let path = maze.find_path((0, 0), (4, 3)).unwrap();
+        assert_eq!(path.len(), 12);
+        assert_eq!((path[0], path[11]), ((0, 0), (4, 3)));
+        assert!(path.iter().all(|&(x, y)| !maze.get(x, y)));
+        assert!(path
+            .windows(2)
+            .all(|step| (step[0].0 - step[1].0).abs() + (step[0].1 - step[1].1).abs() == 1));
+
+        // Diagonal steps never cut the corner of a wall
+        let diagonal = maze.find_path_with((0, 0), (4, 3), true).unwrap();
+        assert_eq!(diagonal.len(), 12);
+        let room = map("....\n....\n....\n....\n");
+        assert_eq!(
+            room.find_path_with((0, 0), (3, 3), true),
+            Some(vec![(0, 0), (1, 1), (2, 2), (3, 3)])
+        );
+
+        // Walled off or starting in a wall there's no way
+        maze.set(4, 2, true);
+        assert_eq!(maze.find_path((0, 0), (4, 3)), None);
+        assert_eq!(maze.find_path((1, 0), (0, 0)), None);
+
+        // Outside of the chunks is walkable with the default edge mode, within a tile of them
+        let open = map("###\n");
+        assert_eq!(
+            open.find_path((-1, 0), (3, 0)).map(|path| path.len()),
+            Some(7)
+        );
+    }
+}
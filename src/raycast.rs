@@ -0,0 +1,120 @@
+use crate::tilemap::TileMap;
+use crate::types::Vector2;
+
+/// Tiles on the Bresenham line from `(x0, y0)` to `(x1, y1)`, both ends included
+pub(crate) fn line_tiles(x0: i32, y0: i32, x1: i32, y1: i32) -> Vec<(i32, i32)> {
+    let (dx, dy) = ((x1 - x0).abs(), -(y1 - y0).abs());
+    let (step_x, step_y) = ((x1 - x0).signum(), (y1 - y0).signum());
+    let mut error = dx + dy;
+    let (mut x, mut y) = (x0, y0);
+
+    let mut tiles = Vec::with_capacity((dx - dy + 1) as usize);
+    loop {
+        tiles.push((x, y));
+        if x == x1 && y == y1 {
+            return tiles;
+        }
+
+        let doubled = 2 * error;
+        if doubled >= dy {
+            error += dy;
+            x += step_x;
+        }
+        if doubled <= dx {
+            error += dx;
+            y += step_y;
+        }
+    }
+}
+
+impl TileMap {
+    /// Whether no filled tile lies on the Bresenham line between two tiles. The ends
+    /// themselves aren't checked, so a wall can see along itself.
+    pub fn line_of_sight(&self, from: (i32, i32), to: (i32, i32)) -> bool {
+        let tiles = line_tiles(from.0, from.1, to.0, to.1);
+        !tiles
+            .iter()
+            .skip(1)
+            .take(tiles.len().saturating_sub(2))
+            .any(|&(x, y)| self.get(x, y))
+    }
+
+    /// Walks the tiles along a ray in world space (screen space without a camera) and
+    /// returns the first filled one within `max` units, the one holding `origin` included.
+    /// Tile `(x, y)` covers `x * width` to `(x + 1) * width` at the current scale.
+    pub fn raycast(&self, origin: Vector2, dir: Vector2, max: f32) -> Option<(i32, i32)> {
+        let size = self.scaled_tile_size();
+        let length = dir.length();
+        if size.x <= 0.0 || size.y <= 0.0 || length <= 0.0 {
+            return None;
+        }
+        let dir = Vector2::new(dir.x / length, dir.y / length);
+
+        let (mut x, mut y) = self.screen_to_tile(origin);
+        let step_x = if dir.x > 0.0 { 1 } else { -1 };
+        let step_y = if dir.y > 0.0 { 1 } else { -1 };
+
+        // Distance along the ray to cross a whole tile, and to reach the next tile border
+        let delta_x = (size.x / dir.x).abs();
+        let delta_y = (size.y / dir.y).abs();
+        let border_x = if step_x > 0 { (x + 1) as f32 } else { x as f32 } * size.x;
+        let border_y = if step_y > 0 { (y + 1) as f32 } else { y as f32 } * size.y;
+        let mut next_x = if dir.x == 0.0 {
+            f32::INFINITY
+        } else {
+            (border_x - origin.x) / dir.x
+        };
+        let mut next_y = if dir.y == 0.0 {
+            f32::INFINITY
+        } else {
+            (border_y - origin.y) / dir.y
+        };
+
+        let mut distance = 0.0;
+        while distance <= max {
+            if self.get(x, y) {
+                return Some((x, y));
+            }
+
+            if next_x < next_y {
+                distance = next_x;
+                next_x += delta_x;
+                x += step_x;
+            } else {
+                distance = next_y;
+                next_y += delta_y;
+                y += step_y;
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tilemap::tests::map;
+
+    #[test]
+    fn walls_block_the_line_of_sight() {
+        let room = map(".....\n..#..\n.....\n").with_scale(1.0);
+        assert!(!room.line_of_sight((0, 1), (4, 1)));
+        assert!(room.line_of_sight((0, 0), (4, 0)));
+        assert!(room.line_of_sight((0, 2), (4, 2)));
+        assert!(room.line_of_sight((2, 1), (2, 0)));
+
+        // Rays go in world space, a tile covering 8 units at scale 1
+        let origin = Vector2::new(4.0, 12.0);
+        assert_eq!(
+            room.raycast(origin, Vector2::new(1.0, 0.0), 100.0),
+            Some((2, 1))
+        );
+        assert_eq!(room.raycast(origin, Vector2::new(1.0, 0.0), 10.0), None);
+        assert_eq!(room.raycast(origin, Vector2::new(-1.0, 0.0), 100.0), None);
+        assert_eq!(
+            room.raycast(Vector2::new(20.0, 0.0), Vector2::new(0.0, 1.0), 100.0),
+            Some((2, 1))
+        );
+    }
+}
@@ -0,0 +1,117 @@
+use std::collections::HashSet;
+
+use crate::tilemap::TileMap;
+
+impl TileMap {
+    /// Groups the filled tiles into 4-connected islands, spreading across touching chunks.
+    /// Each island lists the world coordinates of its tiles, tiles outside of every chunk
+    /// never count as filled whatever the edge mode.
+    pub fn connected_components(&self) -> Vec<Vec<(i32, i32)>> {
+        self.components(true)
+    }
+
+    /// Clears every island smaller than `min_size` tiles, like the specks a cellular
+    /// automaton leaves behind
+    pub fn remove_small_islands(&mut self, min_size: usize) {
+        self.fill_components(true, min_size, 0);
+    }
+
+    /// Fills every enclosed 4-connected pocket of empty tiles smaller than `min_size`,
+    /// the counterpart of `remove_small_islands`. Empty areas touching the edge of the
+    /// chunks are left alone.
+    pub fn fill_small_holes(&mut self, min_size: usize) {
+        self.fill_components(false, min_size, 1);
+    }
+
+    fn fill_components(&mut self, filled: bool, min_size: usize, tile: u8) {
+        let small: Vec<Vec<(i32, i32)>> = self
+            .components(filled)
+            .into_iter()
+            .filter(|component| component.len() < min_size)
+            .filter(|component| filled || !self.touches_edge(component))
+            .collect();
+
+        self.batch(|map| {
+            for (x, y) in small.into_iter().flatten() {
+                map.set_tile(x, y, tile);
+            }
+        });
+    }
+
+    /// Whether a tile of the component has a neighbor outside of every chunk
+    fn touches_edge(&self, component: &[(i32, i32)]) -> bool {
+        component.iter().any(|&(x, y)| {
+            [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)]
+                .iter()
+                .any(|&(x, y)| self.get_chunk_at(x, y).is_none())
+        })
+    }
+
+    /// 4-connected components of the filled or the empty tiles inside the chunks
+    fn components(&self, filled: bool) -> Vec<Vec<(i32, i32)>> {
+        let matches = |x: i32, y: i32| match self.get_chunk_at(x, y) {
+            Some(chunk) => chunk.get(x - chunk.x, y - chunk.y) == filled,
+            None => false,
+        };
+
+        let mut visited: HashSet<(i32, i32)> = HashSet::new();
+        let mut components = vec![];
+        for chunk in self.chunks.iter() {
+            for y in chunk.y..chunk.y + chunk.size_y {
+                for x in chunk.x..chunk.x + chunk.size_x {
+                    if !matches(x, y) || !visited.insert((x, y)) {
+                        continue;
+                    }
+
+                    let mut component = vec![];
+                    let mut stack = vec![(x, y)];
+                    while let Some((x, y)) = stack.pop() {
+                        component.push((x, y));
+                        for next in [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)] {
+                            if matches(next.0, next.1) && visited.insert(next) {
+                                stack.push(next);
+                            }
+                        }
+                    }
+                    components.push(component);
+                }
+            }
+        }
+        components
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tilemap::tests::map;
+
+    #[test]
+    fn separate_blobs_are_separate_components() {
+        let islands = map("##...\n##..#\n...##\n");
+        let mut sizes: Vec<usize> = islands
+            .connected_components()
+            .iter()
+            .map(|component| component.len())
+            .collect();
+        sizes.sort_unstable();
+        assert_eq!(sizes, vec![3, 4]);
+
+        // Diagonal neighbors don't connect
+        assert_eq!(map("#.\n.#\n").connected_components().len(), 2);
+    }
+
+    #[test]
+    fn small_islands_and_holes_go_away() {
+        let mut cave = map("###....\n###..##\n###....\n");
+        cave.remove_small_islands(5);
+        assert_eq!(cave.to_ascii(), "###....\n###....\n###....\n");
+
+        // The enclosed pocket is filled, the empty area along the edge stays
+        let mut walls = map("#####.\n#..##.\n#####.\n");
+        walls.enable_history(10);
+        walls.fill_small_holes(5);
+        assert_eq!(walls.to_ascii(), "#####.\n#####.\n#####.\n");
+        assert!(walls.undo());
+        assert_eq!(walls.to_ascii(), "#####.\n#..##.\n#####.\n");
+    }
+}
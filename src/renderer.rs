@@ -0,0 +1,94 @@
+#[cfg(feature = "render")]
+use raylib::prelude::RaylibDraw;
+
+#[cfg(feature = "render")]
+use crate::tilemap::TileRules;
+use crate::tilemap::{TileRule, TileSprite};
+#[cfg(feature = "render")]
+use crate::types::Vector2;
+use crate::types::{Color, Rectangle};
+
+/// Receives every tile `TileMap::draw_to` draws, so the map can be drawn by any backend
+pub trait TileRenderer {
+    /// Draws `sprite`, the sprite `rule` shows for this tile, stretched over `dest`
+    fn draw_tile(&mut self, rule: &TileRule, sprite: &TileSprite, dest: Rectangle, tint: Color);
+
+    /// Fills `rect` with `color`, for `TileMap::draw_minimap_to`. Draws nothing unless the
+    /// backend overrides it.
+    fn draw_rect(&mut self, _rect: Rectangle, _color: Color) {}
+}
+
+/// Draws the tiles with raylib, from the textures of the rules they came from
+#[cfg(feature = "render")]
+pub struct RaylibRenderer<'a, D: RaylibDraw> {
+    d: &'a mut D,
+    rules: &'a TileRules,
+}
+
+#[cfg(feature = "render")]
+impl<'a, D: RaylibDraw> RaylibRenderer<'a, D> {
+    pub fn new(d: &'a mut D, rules: &'a TileRules) -> Self {
+        Self { d, rules }
+    }
+}
+
+#[cfg(feature = "render")]
+impl<D: RaylibDraw> TileRenderer for RaylibRenderer<'_, D> {
+    fn draw_tile(&mut self, _rule: &TileRule, sprite: &TileSprite, dest: Rectangle, tint: Color) {
+        self.d.draw_texture_pro(
+            self.rules.texture(sprite),
+            sprite.source,
+            dest,
+            Vector2::new(0.0, 0.0),
+            0.0,
+            tint,
+        );
+    }
+
+    fn draw_rect(&mut self, rect: Rectangle, color: Color) {
+        self.d.draw_rectangle_rec(rect, color);
+    }
+}
+
+/// A single `draw_tile` call seen by `RecordingRenderer`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DrawCall {
+    pub neighbors: [u8; 4], // Of the rule drawn, see TileRule::neighbors
+    pub mask: u8,           // Of the rule drawn in RuleMode::Blob
+    pub sprite: TileSprite,
+    pub dest: Rectangle,
+    pub tint: Color,
+}
+
+/// Keeps every tile it's asked to draw instead of drawing it, to check what a map draws
+/// without a window
+#[derive(Clone, Debug, Default)]
+pub struct RecordingRenderer {
+    pub calls: Vec<DrawCall>,
+    pub rects: Vec<(Rectangle, Color)>, // Filled with draw_rect
+}
+
+impl RecordingRenderer {
+    pub fn new() -> Self {
+        Self {
+            calls: vec![],
+            rects: vec![],
+        }
+    }
+}
+
+impl TileRenderer for RecordingRenderer {
+    fn draw_tile(&mut self, rule: &TileRule, sprite: &TileSprite, dest: Rectangle, tint: Color) {
+        self.calls.push(DrawCall {
+            neighbors: rule.neighbors,
+            mask: rule.mask,
+            sprite: *sprite,
+            dest,
+            tint,
+        });
+    }
+
+    fn draw_rect(&mut self, rect: Rectangle, color: Color) {
+        self.rects.push((rect, color));
+    }
+}
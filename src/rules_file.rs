@@ -0,0 +1,327 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use crate::error::TileError;
+use crate::tilemap::{AtlasLayout, RuleMode};
+use crate::types::Rectangle;
+
+// Yaml:
+// size: 16 # Or { w: 16, h: 8 } for non square tiles
+// margin: 1 # Optional, with margin or spacing set sprite x and y are tile indices
+// spacing: 2 # instead of pixels
+// mode: dual_grid # Optional, or blob for neighbors lists of 8 (see RuleMode::Blob)
+// atlases: { dirt: "resources/dirt.png" } # Optional, more atlases next to with_sprite_atlas's
+//
+// rules:
+//   - neighbors: [false, false, true, false]
+//     sprite: { x: 0, y: 0 }
+//   ...
+//   - neighbors: [2, 2, 1, 0] # Material ids, true and false are 1 and 0
+//     sprite: { x: 48, y: 48 }
+//   - neighbors: [true, false, false, false]
+//     sprite: [{ x: 0, y: 48, weight: 3 }, { x: 16, y: 48 }] # Variants, picked per tile by weight
+//   - neighbors: [true, true, true, true]
+//     frames: [{ x: 0, y: 64 }, { x: 16, y: 64 }] # Animated instead of a single sprite
+//     frame_time: 0.25
+//   - neighbors: [false, true, true, true]
+//     sprite: { x: 0, y: 0, atlas: dirt } # From a named atlas instead of the main one
+//
+// Json follows the same shape:
+// { "size": 16, "rules": [{ "neighbors": [false, false, true, false], "sprite": { "x": 0, "y": 0 } }] }
+//
+// Transitions yaml, dual grid rules between named materials (see TileRules::load_transitions):
+// size: 16 # margin, spacing and atlases as above
+// materials: { grass: 1, sand: 2 } # Material ids, "empty" is 0 unless named otherwise
+// transitions:
+//   - corners: [grass, grass, sand, sand] # Left top, right top, left bottom, right bottom
+//     sprite: { x: 0, y: 0 } # Or variants and frames like a rule
+//   - corners: [sand, empty, empty, sand]
+//     sprite: { x: 16, y: 0 }
+
+/// Text of a rules file along with the format it is written in
+pub(crate) enum RulesSource {
+    Yaml(String),
+    Json(String),
+    #[cfg(feature = "ron")]
+    Ron(String),
+    Transitions(String), // Yaml
+}
+
+impl RulesSource {
+    /// Reads `path` again, keeping the format
+    #[cfg(feature = "render")]
+    pub(crate) fn reread(&self, path: &str) -> Result<RulesSource, TileError> {
+        match self {
+            RulesSource::Yaml(_) => std::fs::read_to_string(path)
+                .map(RulesSource::Yaml)
+                .map_err(TileError::ReadYaml),
+            RulesSource::Json(_) => std::fs::read_to_string(path)
+                .map(RulesSource::Json)
+                .map_err(TileError::ReadJson),
+            RulesSource::Transitions(_) => std::fs::read_to_string(path)
+                .map(RulesSource::Transitions)
+                .map_err(TileError::ReadYaml),
+            #[cfg(feature = "ron")]
+            RulesSource::Ron(_) => std::fs::read_to_string(path)
+                .map(RulesSource::Ron)
+                .map_err(TileError::ReadRon),
+        }
+    }
+
+    pub(crate) fn parse(&self) -> Result<RulesFile, TileError> {
+        match self {
+            RulesSource::Yaml(text) => serde_yaml::from_str(text).map_err(TileError::ParseYaml),
+            RulesSource::Json(text) => serde_json::from_str(text).map_err(TileError::ParseJson),
+            #[cfg(feature = "ron")]
+            // Optional fields are written without `Some(...)`, like in the other formats
+            RulesSource::Ron(text) => ron::Options::default()
+                .with_default_extension(ron::extensions::Extensions::IMPLICIT_SOME)
+                .from_str(text)
+                .map_err(TileError::ParseRon),
+            RulesSource::Transitions(text) => serde_yaml::from_str::<TransitionsFile>(text)
+                .map_err(TileError::ParseYaml)?
+                .into_rules_file(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct RulesFile {
+    pub size: SizeSpec,
+    pub margin: Option<i32>,
+    pub spacing: Option<i32>,
+    pub mode: Option<ModeSpec>,
+    #[serde(default)]
+    pub atlases: HashMap<String, String>, // Name to image path
+    pub rules: Vec<RuleSpec>,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ModeSpec {
+    DualGrid,
+    Blob,
+}
+
+impl RulesFile {
+    /// A file without any rules, for rules built in code
+    pub fn new(size_x: i32, size_y: i32) -> Self {
+        Self {
+            size: SizeSpec::Rect {
+                w: size_x,
+                h: size_y,
+            },
+            margin: None,
+            spacing: None,
+            mode: None,
+            atlases: HashMap::new(),
+            rules: vec![],
+        }
+    }
+
+    pub fn mode(&self) -> RuleMode {
+        match self.mode {
+            None | Some(ModeSpec::DualGrid) => RuleMode::DualGrid,
+            Some(ModeSpec::Blob) => RuleMode::Blob,
+        }
+    }
+
+    /// Layout given by the file, `None` if it uses raw pixel coordinates
+    pub fn layout(&self) -> Option<AtlasLayout> {
+        if self.margin.is_none() && self.spacing.is_none() {
+            return None;
+        }
+
+        Some(AtlasLayout {
+            margin: self.margin.unwrap_or(0),
+            spacing: self.spacing.unwrap_or(0),
+        })
+    }
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(untagged)]
+pub(crate) enum SizeSpec {
+    Square(i32),
+    Rect { w: i32, h: i32 },
+}
+
+impl SizeSpec {
+    pub fn size(self) -> (i32, i32) {
+        match self {
+            SizeSpec::Square(size) => (size, size),
+            SizeSpec::Rect { w, h } => (w, h),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct RuleSpec {
+    pub neighbors: Vec<NeighborSpec>, // 4 corners, or 8 neighbors in blob mode
+    pub sprite: Option<SpriteList>,
+    pub frames: Option<Vec<SpriteSpec>>,
+    pub frame_time: Option<f32>,
+}
+
+impl RuleSpec {
+    /// A static rule drawing the sprite at `rect`, see `TileRules::add_rule`
+    pub fn from_rect(neighbors: [bool; 4], rect: Rectangle) -> Self {
+        Self {
+            neighbors: neighbors.map(NeighborSpec::Filled).to_vec(),
+            sprite: Some(SpriteList::One(SpriteSpec {
+                x: rect.x as i64,
+                y: rect.y as i64,
+                weight: None,
+                atlas: None,
+            })),
+            frames: None,
+            frame_time: None,
+        }
+    }
+
+    /// Source rects of every frame along with the atlas they're in, `None` for the main one.
+    /// A static rule has a single frame.
+    pub fn sprite_rects(
+        &self,
+        size: (i32, i32),
+        layout: Option<AtlasLayout>,
+    ) -> Result<Vec<(Rectangle, Option<&str>)>, TileError> {
+        match (&self.frames, &self.sprite) {
+            (Some(frames), _) if !frames.is_empty() => Ok(frames
+                .iter()
+                .map(|frame| (frame.rect(size, layout), frame.atlas.as_deref()))
+                .collect()),
+            (None, Some(SpriteList::One(sprite))) => {
+                Ok(vec![(sprite.rect(size, layout), sprite.atlas.as_deref())])
+            }
+            // The frames come from the variants
+            (None, Some(SpriteList::Many(sprites))) if !sprites.is_empty() => Ok(vec![]),
+            _ => Err(TileError::InvalidSprite),
+        }
+    }
+
+    /// Source rects, atlases and weights of the sprite variants, empty unless `sprite` is a list
+    pub fn variant_rects(
+        &self,
+        size: (i32, i32),
+        layout: Option<AtlasLayout>,
+    ) -> Vec<(Rectangle, Option<&str>, f32)> {
+        match (&self.frames, &self.sprite) {
+            (None, Some(SpriteList::Many(sprites))) => sprites
+                .iter()
+                .map(|sprite| {
+                    (
+                        sprite.rect(size, layout),
+                        sprite.atlas.as_deref(),
+                        sprite.weight.unwrap_or(1.0),
+                    )
+                })
+                .collect(),
+            _ => vec![],
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct TransitionsFile {
+    pub size: SizeSpec,
+    pub margin: Option<i32>,
+    pub spacing: Option<i32>,
+    #[serde(default)]
+    pub atlases: HashMap<String, String>,
+    pub materials: HashMap<String, u8>, // Name to material id
+    pub transitions: Vec<TransitionSpec>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct TransitionSpec {
+    pub corners: Vec<String>, // Material names, in the order of TileRule::neighbors
+    pub sprite: Option<SpriteList>,
+    pub frames: Option<Vec<SpriteSpec>>,
+    pub frame_time: Option<f32>,
+}
+
+impl TransitionsFile {
+    /// The dual grid rules file the transitions stand for, corners turned into material ids
+    pub fn into_rules_file(self) -> Result<RulesFile, TileError> {
+        let mut rules = Vec::with_capacity(self.transitions.len());
+        for transition in self.transitions {
+            let neighbors = transition
+                .corners
+                .iter()
+                .map(|name| match self.materials.get(name) {
+                    Some(&material) => Ok(NeighborSpec::Material(material)),
+                    None if name == "empty" => Ok(NeighborSpec::Material(0)),
+                    None => Err(TileError::UnknownMaterial(name.clone())),
+                })
+                .collect::<Result<Vec<NeighborSpec>, TileError>>()?;
+            rules.push(RuleSpec {
+                neighbors,
+                sprite: transition.sprite,
+                frames: transition.frames,
+                frame_time: transition.frame_time,
+            });
+        }
+
+        Ok(RulesFile {
+            size: self.size,
+            margin: self.margin,
+            spacing: self.spacing,
+            mode: None,
+            atlases: self.atlases,
+            rules,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub(crate) enum SpriteList {
+    One(SpriteSpec),
+    Many(Vec<SpriteSpec>),
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(untagged)]
+pub(crate) enum NeighborSpec {
+    Filled(bool),
+    Material(u8),
+}
+
+impl NeighborSpec {
+    pub fn material(self) -> u8 {
+        match self {
+            NeighborSpec::Filled(filled) => filled as u8,
+            NeighborSpec::Material(material) => material,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct SpriteSpec {
+    pub x: i64,
+    pub y: i64,
+    pub weight: Option<f32>,   // Only used by variants, 1 by default
+    pub atlas: Option<String>, // Name in RulesFile::atlases, the main atlas by default
+}
+
+impl SpriteSpec {
+    /// Atlas pixels of the sprite, `x` and `y` being tile indices when there is a layout
+    pub fn rect(&self, (size_x, size_y): (i32, i32), layout: Option<AtlasLayout>) -> Rectangle {
+        let (x, y) = match layout {
+            None => (self.x, self.y),
+            Some(layout) => (
+                layout.margin as i64 + self.x * (size_x + layout.spacing) as i64,
+                layout.margin as i64 + self.y * (size_y + layout.spacing) as i64,
+            ),
+        };
+
+        Rectangle::new(x as f32, y as f32, size_x as f32, size_y as f32)
+    }
+}
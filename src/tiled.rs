@@ -0,0 +1,49 @@
+use crate::error::TileError;
+use crate::tilemap::{TileMap, TileRules};
+
+// A Tiled CSV layer, as exported or found inside <data encoding="csv"> of a .tmx:
+// 0,0,1,1,
+// 0,3,2,1,
+// 0,0,0,1
+// Rows but the last end with a comma, gids can carry Tiled's flip flags in their top bits.
+
+/// Builds a map holding a single chunk at the origin from a Tiled CSV layer, a tile per gid
+/// filled for every nonzero gid. Shorter rows are padded with empty tiles, like `from_ascii`.
+pub fn import_tiled_csv(csv: &str, rules: TileRules) -> Result<TileMap, TileError> {
+    let mut rows: Vec<Vec<u8>> = vec![];
+    for line in csv.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        let mut row = vec![];
+        for cell in line.split(',').map(str::trim) {
+            // The trailing comma leaves an empty cell behind
+            if cell.is_empty() {
+                continue;
+            }
+
+            let gid: u32 = cell
+                .parse()
+                .map_err(|_| TileError::InvalidCsv(cell.to_string()))?;
+            row.push((gid != 0) as u8);
+        }
+        rows.push(row);
+    }
+
+    TileMap::from_rows(&rows, rules)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tilemap::tests::rules;
+
+    #[test]
+    fn nonzero_gids_are_solid() {
+        let csv = "0,0,1,1,\n0,3,2147483650,1,\n0,0,0\n";
+        let map = import_tiled_csv(csv, rules()).unwrap();
+        assert_eq!(map.to_ascii(), "..##\n.###\n....\n");
+
+        assert!(matches!(
+            import_tiled_csv("0,x\n", rules()),
+            Err(TileError::InvalidCsv(cell)) if cell == "x"
+        ));
+    }
+}
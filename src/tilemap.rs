@@ -1,17 +1,71 @@
-use log::{error, info};
-use raylib::prelude::*;
+use std::cell::{Ref, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::ops::Index;
+
+use log::{debug, warn};
+#[cfg(feature = "render")]
+use raylib::prelude::{Texture2D, TextureFilter};
+
+use crate::brush::BrushShape;
+use crate::error::TileError;
+use crate::history::{History, TileChange};
+use crate::raycast::line_tiles;
+use crate::renderer::TileRenderer;
+use crate::rng::cell_unit;
+use crate::rules_file::{NeighborSpec, RuleSpec, RulesFile, RulesSource};
+use crate::types::{Color, Rectangle, Vector2};
+
+#[cfg(feature = "render")]
+mod render;
+#[cfg(test)]
+pub(crate) mod tests;
+
+/// What reading a tile outside of the chunks returns
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EdgeMode {
+    /// Outside tiles are empty
+    #[default]
+    Empty,
+    /// Outside tiles are material 1
+    Filled,
+    /// Coordinates wrap around the edges, for toroidal worlds
+    Wrap,
+    /// Coordinates are clamped to the nearest tile inside
+    Clamp,
+}
 
 pub struct Chunk {
     pub x: i32,
     pub y: i32,
     pub size_x: i32,
     pub size_y: i32,
-    pub data: Vec<Vec<bool>>,
+    pub z: i32, // Highest wins where chunks overlap, set it with TileMap::set_chunk_z
+    pub visible: bool, // Drawn at all, see TileMap::set_chunk_visible
+    pub data: Vec<u8>, // Tile ids, 0 is empty. Row-major, indexed as y * size_x + x
+    pub tints: HashMap<(i32, i32), Color>, // Sparse per tile tints, by local coordinate
+    pub densities: HashMap<(i32, i32), u8>, // Sparse, see TileMap::set_density
+    rule_cache: RefCell<Option<Vec<Option<Option<usize>>>>>, // See TileMap::cached_rules
 }
 
 impl Chunk {
     pub fn new(x: i32, y: i32, size_x: i32, size_y: i32, data: Vec<Vec<bool>>) -> Self {
-        info!(
+        Self::from_flat(
+            x,
+            y,
+            size_x,
+            size_y,
+            data.into_iter().flatten().map(|tile| tile as u8).collect(),
+        )
+    }
+
+    pub fn from_flat(x: i32, y: i32, size_x: i32, size_y: i32, data: Vec<u8>) -> Self {
+        assert_eq!(
+            data.len(),
+            (size_x * size_y) as usize,
+            "Chunk data doesn't match its size"
+        );
+
+        debug!(
             "Chunk created at ({}, {}) with size ({}, {})",
             x, y, size_x, size_y
         );
@@ -20,319 +74,2304 @@ impl Chunk {
             y,
             size_x,
             size_y,
+            z: 0,
+            visible: true,
             data,
+            tints: HashMap::new(),
+            densities: HashMap::new(),
+            rule_cache: RefCell::new(None),
         }
     }
 
-    pub fn get(&self, x: i32, y: i32) -> bool {
+    /// Whether the world coordinate falls inside this chunk
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x && x < self.x + self.size_x && y >= self.y && y < self.y + self.size_y
+    }
+
+    pub fn get_tile(&self, x: i32, y: i32) -> u8 {
         if x < 0 || x >= self.size_x || y < 0 || y >= self.size_y {
-            return false;
+            return 0;
+        }
+
+        self.data[(y * self.size_x + x) as usize]
+    }
+
+    pub fn set_tile(&mut self, x: i32, y: i32, tile: u8) {
+        if x < 0 || x >= self.size_x || y < 0 || y >= self.size_y {
+            return;
+        }
+
+        self.data[(y * self.size_x + x) as usize] = tile;
+    }
+
+    /// Same as `get_tile`, but out of range coordinates follow `edge`
+    pub fn get_tile_with_edge(&self, x: i32, y: i32, edge: EdgeMode) -> u8 {
+        if x >= 0 && x < self.size_x && y >= 0 && y < self.size_y {
+            return self.get_tile(x, y);
+        }
+
+        match edge {
+            EdgeMode::Empty => 0,
+            EdgeMode::Filled => 1,
+            _ if self.size_x <= 0 || self.size_y <= 0 => 0,
+            EdgeMode::Wrap => self.get_tile(x.rem_euclid(self.size_x), y.rem_euclid(self.size_y)),
+            EdgeMode::Clamp => {
+                self.get_tile(x.clamp(0, self.size_x - 1), y.clamp(0, self.size_y - 1))
+            }
         }
+    }
+
+    /// Whether the tile holds any material
+    pub fn get(&self, x: i32, y: i32) -> bool {
+        self.get_tile(x, y) != 0
+    }
 
-        self.data[y as usize][x as usize]
+    pub fn get_with_edge(&self, x: i32, y: i32, edge: EdgeMode) -> bool {
+        self.get_tile_with_edge(x, y, edge) != 0
     }
 
+    /// Sets the tile to material 1 when `value` is true, or clears it
     pub fn set(&mut self, x: i32, y: i32, value: bool) {
+        self.set_tile(x, y, value as u8);
+    }
+
+    pub fn get_tint(&self, x: i32, y: i32) -> Option<Color> {
+        self.tints.get(&(x, y)).copied()
+    }
+
+    pub fn set_tint(&mut self, x: i32, y: i32, tint: Color) {
         if x < 0 || x >= self.size_x || y < 0 || y >= self.size_y {
             return;
         }
 
-        self.data[y as usize][x as usize] = value;
+        self.tints.insert((x, y), tint);
+    }
+
+    pub fn clear_tint(&mut self, x: i32, y: i32) {
+        self.tints.remove(&(x, y));
+    }
+
+    /// Clears a filled tile or fills an empty one, returning the new value,
+    /// or `None` if the coordinate is out of range
+    pub fn toggle(&mut self, x: i32, y: i32) -> Option<bool> {
+        if x < 0 || x >= self.size_x || y < 0 || y >= self.size_y {
+            return None;
+        }
+
+        let value = !self.get(x, y);
+        self.set(x, y, value);
+        Some(value)
+    }
+
+    /// Changes the size of the chunk keeping its origin. Tiles inside both sizes keep their
+    /// value, new ones are empty and the ones past the new size are dropped with their tints.
+    /// Resize chunks of a map with `TileMap::resize_chunk` so it sees the new size.
+    pub fn resize(&mut self, new_x: i32, new_y: i32) {
+        let (new_x, new_y) = (new_x.max(0), new_y.max(0));
+        let mut data = vec![0; (new_x * new_y) as usize];
+        for y in 0..new_y.min(self.size_y) {
+            for x in 0..new_x.min(self.size_x) {
+                data[(y * new_x + x) as usize] = self.get_tile(x, y);
+            }
+        }
+
+        self.data = data;
+        self.size_x = new_x;
+        self.size_y = new_y;
+        self.tints.retain(|&(x, y), _| x < new_x && y < new_y);
+        self.densities.retain(|&(x, y), _| x < new_x && y < new_y);
+        self.rule_cache.replace(None);
+    }
+}
+
+/// How the rules are matched against the map and drawn
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RuleMode {
+    /// Every drawn cell sits between four tiles, half a tile off the grid, and is matched
+    /// by those 4 corners (see `TileRule::neighbors`)
+    #[default]
+    DualGrid,
+    /// Every filled tile is drawn on the grid, matched by its 8 neighbors (see `blob_mask`)
+    Blob,
+}
+
+/// Packs the 8 neighbors of a tile into a blob mask. They go clockwise from the top:
+/// top, top right, right, bottom right, bottom, bottom left, left, top left, the first
+/// being the lowest bit. A corner only counts when both sides next to it are filled,
+/// which leaves the 47 masks blob tilesets draw.
+pub fn blob_mask(neighbors: [bool; 8]) -> u8 {
+    let mut neighbors = neighbors;
+    for corner in [1, 3, 5, 7] {
+        neighbors[corner] &= neighbors[corner - 1] && neighbors[(corner + 1) % 8];
     }
+
+    neighbors
+        .iter()
+        .enumerate()
+        .fold(0, |mask, (i, &n)| mask | ((n as u8) << i))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TileSprite {
+    pub texture: usize, // Index into TileRules::textures
+    pub source: Rectangle,
 }
 
 pub struct TileRule {
-    pub neighbors: [bool; 4], // Left Top, Right Top, Right Bottom, Left Bottom
-    pub sprite: Texture2D,
-    pub size: i32,
+    pub neighbors: [u8; 4], // Left Top, Right Top, Left Bottom, Right Bottom, see CORNERS
+    pub mask: u8,           // Blob mask in RuleMode::Blob, the neighbors are unused then
+    pub frames: Vec<TileSprite>, // A single frame for static tiles
+    pub variants: Vec<(TileSprite, f32)>, // Weighted static sprites picked per tile, if any
+    pub frame_time: f32,    // Seconds per frame
+    pub size_x: i32,
+    pub size_y: i32,
+}
+
+impl TileRule {
+    /// Returns the sprite drawn at the world cell `(x, y)`. Rules with variants pick one by
+    /// weight from a hash of the cell and `seed`, so a cell keeps its sprite every frame.
+    /// Others show their frame after `time` seconds of animation.
+    pub fn sprite_at(&self, time: f32, x: i32, y: i32, seed: u64) -> &TileSprite {
+        let total: f32 = self
+            .variants
+            .iter()
+            .map(|&(_, weight)| weight.max(0.0))
+            .sum();
+        if total <= 0.0 {
+            return self.frame(time);
+        }
+
+        let roll = cell_unit(x, y, seed) * total;
+        let mut sum = 0.0;
+        for (sprite, weight) in self.variants.iter() {
+            sum += weight.max(0.0);
+            if roll < sum {
+                return sprite;
+            }
+        }
+        self.frame(time)
+    }
+
+    /// Returns the frame to show after `time` seconds of animation
+    pub fn frame(&self, time: f32) -> &TileSprite {
+        if self.frames.len() < 2 || self.frame_time <= 0.0 {
+            return &self.frames[0];
+        }
+
+        let frame = (time / self.frame_time) as usize % self.frames.len();
+        &self.frames[frame]
+    }
+}
+
+/// Offsets from the tile at the top left of a dual grid cell to the 4 tiles at its corners,
+/// in the order of `TileRule::neighbors`: left top, right top, left bottom, right bottom
+pub const CORNERS: [(i32, i32); 4] = [(0, 0), (1, 0), (0, 1), (1, 1)];
+
+/// Packs a neighbor combination into a 0-15 index, the first corner (see `CORNERS`) being
+/// the lowest bit
+pub fn neighbors_to_index(neighbors: [bool; 4]) -> u8 {
+    neighbors
+        .iter()
+        .enumerate()
+        .fold(0, |index, (i, &n)| index | ((n as u8) << i))
+}
+
+/// Index of a material pattern made only of empty and material 1 corners
+fn pattern_to_index(pattern: [u8; 4]) -> Option<usize> {
+    if pattern.iter().any(|&tile| tile > 1) {
+        return None;
+    }
+
+    Some(neighbors_to_index(pattern.map(|tile| tile != 0)) as usize)
+}
+
+/// Unpacks a 0-15 index made by `neighbors_to_index`, higher bits are ignored
+pub fn index_to_neighbors(index: u8) -> [bool; 4] {
+    [
+        index & 1 != 0,
+        index & 2 != 0,
+        index & 4 != 0,
+        index & 8 != 0,
+    ]
+}
+
+/// Border around a tilesheet and gap between its tiles, both in pixels
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub struct AtlasLayout {
+    pub margin: i32,
+    pub spacing: i32,
 }
 
 pub struct TileRules {
     pub rules: Vec<TileRule>,
+    #[cfg(feature = "render")]
+    pub textures: Vec<Texture2D>,
+    // Atlas (None for the main one) and rect of every texture, None for a whole shared atlas
+    #[cfg(feature = "render")]
+    texture_sources: Vec<(Option<String>, Option<Rectangle>)>,
+    // Rule index, atlas and atlas rect of every sprite, for reskin
+    #[cfg(feature = "render")]
+    atlas_rects: Vec<(usize, Option<String>, Rectangle)>,
+    #[cfg(feature = "render")]
+    texture_filter: TextureFilter, // Applied to every texture, see set_texture_filter
+    #[cfg(feature = "render")]
+    mipmaps: bool, // Whether every texture gets mipmaps, see generate_mipmaps
+    size: (i32, i32),
+    index: [Option<usize>; 16],
+    material_index: HashMap<[u8; 4], usize>,
+    default_rule: Option<TileRule>,
     sprite_atlas: Option<String>,
-    yaml_file: Option<String>,
+    rules_file: Option<RulesSource>,
+    rules_path: Option<String>, // Set when the rules came from a file on disk, for reload
+    code_rules: Vec<([bool; 4], Rectangle)>, // Added with add_rule
+    check_completeness: bool,
+    strict: bool,
+    shared_atlas: bool,
+    atlas_layout: Option<AtlasLayout>,
+    mode: RuleMode,
+    blob_index: HashMap<u8, usize>,
+    headless: bool,        // Loaded with load_headless, so there's no atlas
+    uses_main_atlas: bool, // Whether some sprite comes from sprite_atlas, not a named atlas
 }
 
 impl TileRules {
     pub fn new() -> Self {
         Self {
             rules: vec![],
+            #[cfg(feature = "render")]
+            textures: vec![],
+            #[cfg(feature = "render")]
+            texture_sources: vec![],
+            #[cfg(feature = "render")]
+            atlas_rects: vec![],
+            #[cfg(feature = "render")]
+            texture_filter: TextureFilter::TEXTURE_FILTER_POINT,
+            #[cfg(feature = "render")]
+            mipmaps: false,
+            size: (0, 0),
+            index: [None; 16],
+            material_index: HashMap::new(),
+            default_rule: None,
             sprite_atlas: None,
-            yaml_file: None,
+            rules_file: None,
+            rules_path: None,
+            code_rules: vec![],
+            check_completeness: true,
+            strict: false,
+            shared_atlas: false,
+            atlas_layout: None,
+            mode: RuleMode::DualGrid,
+            blob_index: HashMap::new(),
+            headless: false,
+            uses_main_atlas: false,
         }
     }
 
-    pub fn with_sprite_atlas(mut self, sprite_atlas: &str) -> Self {
-        self.sprite_atlas = Some(sprite_atlas.to_string());
+    /// The mode given by the rules file, dual grid unless it sets `mode: blob`
+    pub fn mode(&self) -> RuleMode {
+        self.mode
+    }
+
+    /// Reads sprite `x` and `y` as tile indices on a tilesheet with a `margin` border and
+    /// `spacing` between tiles, overriding the layout of the rules file. Without a layout
+    /// from either, they are raw pixel coordinates.
+    pub fn with_atlas_layout(mut self, margin: i32, spacing: i32) -> Self {
+        self.atlas_layout = Some(AtlasLayout { margin, spacing });
         self
     }
 
-    pub fn with_yaml_file(mut self, yaml_file: &str) -> Self {
-        let file_data = match std::fs::read_to_string(yaml_file) {
-            Ok(data) => data,
-            Err(e) => {
-                error!("Failed to read the {} file: {}", yaml_file, e);
-                std::process::exit(1);
-            }
-        };
+    /// Keep the whole atlas as one texture and draw every rule from a source rect of it,
+    /// instead of cropping a texture per rule
+    pub fn with_shared_atlas(mut self, shared_atlas: bool) -> Self {
+        self.shared_atlas = shared_atlas;
+        self
+    }
 
-        self.yaml_file = Some(file_data);
+    /// Whether `load` fails on rules matching the same neighbors as an earlier one, instead of
+    /// warning about them. Either way only the first of them is ever drawn. Off by default.
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
         self
     }
 
-    pub fn with_bytes_yaml_file(mut self, yaml_file: &[u8]) -> Self {
-        self.yaml_file = Some(std::str::from_utf8(yaml_file).unwrap().to_string());
+    /// Whether `load` warns about neighbor combinations the rules don't cover, on by default
+    pub fn with_completeness_check(mut self, check_completeness: bool) -> Self {
+        self.check_completeness = check_completeness;
         self
     }
 
-    pub fn load(mut self, rl: &mut RaylibHandle, thread: &RaylibThread) -> Self {
-        let sprite_atlas = match self.sprite_atlas {
-            None => {
-                error!("Tried to load the tile rules without providing a sprite atlas");
-                std::process::exit(1);
-            }
-            Some(ref sprite_atlas) => sprite_atlas.clone(),
+    pub fn with_sprite_atlas(mut self, sprite_atlas: &str) -> Self {
+        self.sprite_atlas = Some(sprite_atlas.to_string());
+        self
+    }
+
+    pub fn with_yaml_file(mut self, yaml_file: &str) -> Result<Self, TileError> {
+        let file_data = std::fs::read_to_string(yaml_file).map_err(TileError::ReadYaml)?;
+
+        self.rules_file = Some(RulesSource::Yaml(file_data));
+        self.rules_path = Some(yaml_file.to_string());
+        Ok(self)
+    }
+
+    pub fn with_bytes_yaml_file(mut self, yaml_file: &[u8]) -> Result<Self, TileError> {
+        let file_data = std::str::from_utf8(yaml_file).map_err(TileError::InvalidUtf8)?;
+
+        self.rules_file = Some(RulesSource::Yaml(file_data.to_string()));
+        self.rules_path = None;
+        Ok(self)
+    }
+
+    pub fn with_json_file(mut self, json_file: &str) -> Result<Self, TileError> {
+        let file_data = std::fs::read_to_string(json_file).map_err(TileError::ReadJson)?;
+
+        self.rules_file = Some(RulesSource::Json(file_data));
+        self.rules_path = Some(json_file.to_string());
+        Ok(self)
+    }
+
+    pub fn with_bytes_json_file(mut self, json_file: &[u8]) -> Result<Self, TileError> {
+        let file_data = std::str::from_utf8(json_file).map_err(TileError::InvalidUtf8)?;
+
+        self.rules_file = Some(RulesSource::Json(file_data.to_string()));
+        self.rules_path = None;
+        Ok(self)
+    }
+
+    /// Reads the rules from a RON file, with the same fields as the yaml one
+    #[cfg(feature = "ron")]
+    pub fn with_ron_file(mut self, ron_file: &str) -> Result<Self, TileError> {
+        let file_data = std::fs::read_to_string(ron_file).map_err(TileError::ReadRon)?;
+
+        self.rules_file = Some(RulesSource::Ron(file_data));
+        self.rules_path = Some(ron_file.to_string());
+        Ok(self)
+    }
+
+    #[cfg(feature = "ron")]
+    pub fn with_bytes_ron_file(mut self, ron_file: &[u8]) -> Result<Self, TileError> {
+        let file_data = std::str::from_utf8(ron_file).map_err(TileError::InvalidUtf8)?;
+
+        self.rules_file = Some(RulesSource::Ron(file_data.to_string()));
+        self.rules_path = None;
+        Ok(self)
+    }
+
+    /// Reads dual grid rules from a transitions file, where each rule names the material of
+    /// its corners to draw the edges between materials, like grass meeting sand. The corners
+    /// are matched by material id like `neighbors` lists of material ids in a rules file.
+    pub fn with_transitions_file(mut self, yaml_file: &str) -> Result<Self, TileError> {
+        let file_data = std::fs::read_to_string(yaml_file).map_err(TileError::ReadYaml)?;
+
+        self.rules_file = Some(RulesSource::Transitions(file_data));
+        self.rules_path = Some(yaml_file.to_string());
+        Ok(self)
+    }
+
+    pub fn with_bytes_transitions_file(mut self, yaml_file: &[u8]) -> Result<Self, TileError> {
+        let file_data = std::str::from_utf8(yaml_file).map_err(TileError::InvalidUtf8)?;
+
+        self.rules_file = Some(RulesSource::Transitions(file_data.to_string()));
+        self.rules_path = None;
+        Ok(self)
+    }
+
+    /// Reads dual grid rules from the text of a transitions file with its `materials` and
+    /// `transitions`, see `with_transitions_file`. Unlike the builders it's parsed right away,
+    /// so an unknown material or a malformed file fails here instead of at `load`.
+    pub fn load_transitions(mut self, yaml: &str) -> Result<Self, TileError> {
+        let source = RulesSource::Transitions(yaml.to_string());
+        source.parse()?;
+
+        self.rules_file = Some(source);
+        self.rules_path = None;
+        Ok(self)
+    }
+
+    /// Adds a dual grid rule drawing `sprite_rect` of the atlas for the corners, built by the
+    /// next `load` or `finalize` after the rules of the rules file if there is one. The rect
+    /// is read like a sprite of the rules file, its `x` and `y` being tile indices when an
+    /// atlas layout is set, and every rule must have the tile size.
+    pub fn add_rule(&mut self, neighbors: [bool; 4], sprite_rect: Rectangle) {
+        self.code_rules.push((neighbors, sprite_rect));
+    }
+
+    /// The rules file along with the rules added by `add_rule`
+    fn rules_file(&self) -> Result<RulesFile, TileError> {
+        let mut file = match (&self.rules_file, self.code_rules.first()) {
+            (Some(rules_file), _) => match &self.rules_path {
+                Some(path) => rules_file.parse().map_err(|e| e.in_file(path))?,
+                None => rules_file.parse()?,
+            },
+            (None, Some(&(_, rect))) => RulesFile::new(rect.width as i32, rect.height as i32),
+            (None, None) => return Err(TileError::MissingYaml),
         };
 
-        let yaml_file = match self.yaml_file {
-            None => {
-                error!("Tried to load the tile rules without providing a yaml file");
-                std::process::exit(1);
+        for &(neighbors, rect) in self.code_rules.iter() {
+            if (rect.width as i32, rect.height as i32) != file.size.size() {
+                return Err(TileError::InvalidSize);
             }
-            Some(ref yaml_file) => yaml_file.clone(),
+            file.rules.push(RuleSpec::from_rect(neighbors, rect));
+        }
+        Ok(file)
+    }
+
+    /// Parses and indexes the rules without reading the atlas or touching raylib, for using
+    /// the map logic without a window. Every sprite keeps its atlas rect on texture 0, like
+    /// with a shared atlas, but there are no textures to draw them with.
+    pub fn load_headless(mut self) -> Result<Self, TileError> {
+        self.build_with(|_, _, rect| {
+            Ok(TileSprite {
+                texture: 0,
+                source: rect,
+            })
+        })?;
+        self.headless = true;
+        Ok(self)
+    }
+
+    /// Parses the rules file and replaces the rules with it, `sprite` turning the atlas rect
+    /// of every frame and variant into the sprite drawn for it. It's also given the index of
+    /// the rule and the path of the atlas the rect is in, `None` for the main atlas. Nothing
+    /// changes on an error.
+    fn build_with(
+        &mut self,
+        mut sprite: impl FnMut(usize, Option<&str>, Rectangle) -> Result<TileSprite, TileError>,
+    ) -> Result<(), TileError> {
+        let file = self.rules_file()?;
+
+        let size = file.size.size();
+        let mode = file.mode();
+        let neighbor_count = match mode {
+            RuleMode::DualGrid => 4,
+            RuleMode::Blob => 8,
         };
+        if size.0 <= 0 || size.1 <= 0 {
+            return Err(TileError::InvalidSize);
+        }
 
-        let data: serde_yaml::Value = match serde_yaml::from_str(&yaml_file) {
-            Ok(d) => d,
-            Err(e) => {
-                error!("Failed to parse the {} file: {}", yaml_file, e);
-                std::process::exit(1);
+        let layout = self.atlas_layout.or_else(|| file.layout());
+
+        let mut rules: Vec<TileRule> = Vec::with_capacity(file.rules.len());
+        let mut uses_main_atlas = false;
+        for (rule_index, rule) in file.rules.iter().enumerate() {
+            if rule.neighbors.len() != neighbor_count {
+                return Err(TileError::InvalidNeighbors(
+                    rule.neighbors.len(),
+                    neighbor_count,
+                ));
             }
-        };
+            let materials: Vec<u8> = rule
+                .neighbors
+                .iter()
+                .copied()
+                .map(NeighborSpec::material)
+                .collect();
+            let (neighbors, mask) = match mode {
+                RuleMode::DualGrid => ([materials[0], materials[1], materials[2], materials[3]], 0),
+                RuleMode::Blob => {
+                    let mut filled = [false; 8];
+                    for (filled, &material) in filled.iter_mut().zip(materials.iter()) {
+                        *filled = material != 0;
+                    }
+                    ([0; 4], blob_mask(filled))
+                }
+            };
+            let frame_rects = rule.sprite_rects(size, layout)?;
+            let variant_rects = rule.variant_rects(size, layout);
+            let frame_count = frame_rects.len();
+            let frame_time = rule.frame_time.unwrap_or(0.0);
 
-        // Yaml:
-        // size: 16
-        //
-        // rules:
-        //   - neighbors: [0, 0, true, false]
-        //     sprite: { x: 0, y: 0 }
-        //   ...
-        //   - neighbors: [true, 0, 0, false]
-        //     sprite: { x: 48, y: 48 }
-
-        let size = match data["size"].as_i64() {
-            Some(size) => size as i32,
-            None => {
-                error!("Invalid size value");
-                std::process::exit(1);
+            let sprite_rects = frame_rects
+                .into_iter()
+                .chain(variant_rects.iter().map(|&(rect, atlas, _)| (rect, atlas)));
+            let mut frames = Vec::with_capacity(frame_count + variant_rects.len());
+            for (sprite_rect, atlas) in sprite_rects {
+                uses_main_atlas |= atlas.is_none();
+                let atlas_path = match atlas {
+                    None => None,
+                    Some(name) => match file.atlases.get(name) {
+                        Some(path) => Some(path.as_str()),
+                        None => return Err(TileError::UnknownAtlas(name.to_string())),
+                    },
+                };
+                frames.push(sprite(rule_index, atlas_path, sprite_rect)?);
+            }
+            let variants: Vec<(TileSprite, f32)> = frames
+                .split_off(frame_count)
+                .into_iter()
+                .zip(variant_rects.iter().map(|&(_, _, weight)| weight))
+                .collect();
+            // Rules with variants show the first one wherever a single sprite is expected
+            if frames.is_empty() {
+                frames.extend(variants.first().map(|&(sprite, _)| sprite));
             }
-        };
 
-        let rules: Vec<TileRule> = match data["rules"].as_sequence() {
-            Some(rules) => rules
-                .iter()
-                .map(|rule| {
-                    let neighbors = match rule["neighbors"].as_sequence() {
-                        Some(neighbors) => {
-                            let mut n = [false; 4];
-                            for (i, neighbor) in neighbors.iter().enumerate() {
-                                n[i] = match neighbor.as_bool() {
-                                    Some(b) => b,
-                                    None => {
-                                        error!("Invalid neighbor value");
-                                        std::process::exit(1);
-                                    }
-                                };
-                            }
-                            n
-                        }
-                        None => {
-                            error!("Invalid neighbors value");
-                            std::process::exit(1);
-                        }
-                    };
+            rules.push(TileRule {
+                neighbors,
+                mask,
+                frames,
+                variants,
+                frame_time,
+                size_x: size.0,
+                size_y: size.1,
+            });
+        }
 
-                    let sprite_rect = match rule["sprite"].as_mapping() {
-                        Some(sprite) => {
-                            let x = match sprite.get(&serde_yaml::Value::String("x".to_string())) {
-                                Some(x) => match x.as_i64() {
-                                    Some(x) => x as f32,
-                                    None => {
-                                        error!("Invalid x value");
-                                        std::process::exit(1);
-                                    }
-                                },
-                                None => {
-                                    error!("Invalid x value");
-                                    std::process::exit(1);
-                                }
-                            };
-
-                            let y = match sprite.get(&serde_yaml::Value::String("y".to_string())) {
-                                Some(y) => match y.as_i64() {
-                                    Some(y) => y as f32,
-                                    None => {
-                                        error!("Invalid y value");
-                                        std::process::exit(1);
-                                    }
-                                },
-                                None => {
-                                    error!("Invalid y value");
-                                    std::process::exit(1);
-                                }
-                            };
-
-                            Rectangle::new(x, y, size as f32, size as f32)
-                        }
-                        None => {
-                            error!("Invalid sprite value");
-                            std::process::exit(1);
-                        }
-                    };
+        // The first of several rules for the same neighbors is the one drawn
+        let mut seen: HashMap<([u8; 4], u8), usize> = HashMap::new();
+        for (i, rule) in rules.iter().enumerate() {
+            let Some(&first) = seen.get(&(rule.neighbors, rule.mask)) else {
+                seen.insert((rule.neighbors, rule.mask), i);
+                continue;
+            };
 
-                    // Load the sprite as an image, crop it and convert it to a texture
-                    let mut image = match Image::load_image(&sprite_atlas) {
-                        Ok(image) => image,
-                        Err(e) => {
-                            error!("Failed to load the sprite atlas image: {}", e);
-                            std::process::exit(1);
-                        }
-                    };
-                    image.crop(sprite_rect);
-                    let texture = rl.load_texture_from_image(&thread, &image).unwrap();
+            if self.strict {
+                return Err(TileError::DuplicateRule(first, i));
+            }
+            warn!(
+                "Tile rule {} matches the same neighbors as rule {} and will never be drawn",
+                i, first
+            );
+        }
+
+        self.rules = rules;
+        self.size = size;
+        self.mode = mode;
+        self.uses_main_atlas = uses_main_atlas;
+        self.build_index();
 
-                    TileRule {
-                        neighbors,
-                        sprite: texture,
-                        size,
+        if self.check_completeness {
+            match mode {
+                RuleMode::DualGrid => {
+                    let missing = self.missing_combinations();
+                    if !missing.is_empty() {
+                        warn!(
+                            "The tile rules don't cover {} neighbor combinations: {:?}",
+                            missing.len(),
+                            missing
+                        );
                     }
-                })
-                .collect(),
-            None => {
-                error!("Invalid rules value");
-                std::process::exit(1);
+                }
+                RuleMode::Blob => {
+                    let missing = self.missing_blob_masks();
+                    if !missing.is_empty() {
+                        warn!(
+                            "The tile rules don't cover {} blob masks: {:?}",
+                            missing.len(),
+                            missing
+                        );
+                    }
+                }
             }
-        };
+        }
 
-        self.rules = rules;
+        Ok(())
+    }
 
-        self
+    /// Returns every neighbor combination that no rule covers
+    pub fn missing_combinations(&self) -> Vec<[bool; 4]> {
+        (0..16u8)
+            .filter(|&i| self.index[i as usize].is_none())
+            .map(index_to_neighbors)
+            .collect()
     }
 
-    pub fn tile_by_rules(&self, neighbors: [bool; 4]) -> &TileRule {
-        self.check_loaded();
+    /// Returns every one of the 47 reduced blob masks that no rule covers
+    pub fn missing_blob_masks(&self) -> Vec<u8> {
+        let mut masks: Vec<u8> = (0..=255u8)
+            .map(|mask| blob_mask(std::array::from_fn(|i| mask & (1 << i) != 0)))
+            .filter(|mask| !self.blob_index.contains_key(mask))
+            .collect();
+        masks.sort_unstable();
+        masks.dedup();
+        masks
+    }
 
-        match self.rules.iter().find(|rule| rule.neighbors == neighbors) {
-            Some(rule) => &rule,
-            None => {
-                error!("Neighbors value not found in the rules");
-                std::process::exit(1);
+    fn build_index(&mut self) {
+        self.index = [None; 16];
+        self.material_index.clear();
+        self.blob_index.clear();
+
+        // The first rule for a combination wins, like the lookup always did
+        for (i, rule) in self.rules.iter().enumerate() {
+            if self.mode == RuleMode::Blob {
+                self.blob_index.entry(rule.mask).or_insert(i);
+                continue;
+            }
+
+            match pattern_to_index(rule.neighbors) {
+                Some(index) => {
+                    self.index[index].get_or_insert(i);
+                }
+                None => {
+                    self.material_index.entry(rule.neighbors).or_insert(i);
+                }
             }
         }
     }
 
-    pub fn check_loaded(&self) {
-        if self.rules.len() == 0 {
-            error!("Tried to use the tile rules without loading them first");
-            std::process::exit(1);
+    /// Width and height of a tile in atlas pixels
+    pub fn tile_size(&self) -> (i32, i32) {
+        self.size
+    }
+
+    /// Sets the rule used for combinations the rule set doesn't cover, like a magenta
+    /// placeholder. Its sprites index into `textures` like any other rule's, so set it
+    /// again after a `reload`.
+    /// Without a default, `draw` leaves those tiles blank.
+    pub fn set_default(&mut self, rule: TileRule) {
+        self.default_rule = Some(rule);
+    }
+
+    pub fn clear_default(&mut self) {
+        self.default_rule = None;
+    }
+
+    /// Returns the rule for the neighbor combination, the default rule if the rule set
+    /// doesn't cover it, or `None` if there is no default either
+    pub fn try_tile_by_rules(&self, neighbors: [bool; 4]) -> Option<&TileRule> {
+        self.try_tile_by_materials(neighbors.map(|n| n as u8))
+    }
+
+    /// Same as `try_tile_by_rules`, for a pattern of corner material ids
+    pub fn try_tile_by_materials(&self, corners: [u8; 4]) -> Option<&TileRule> {
+        self.rule_or_default(self.rule_index(corners))
+    }
+
+    /// Index in `rules` of the rule for the corners, `None` if the rule set doesn't cover them
+    pub(crate) fn rule_index(&self, corners: [u8; 4]) -> Option<usize> {
+        match pattern_to_index(corners) {
+            Some(index) => self.index[index],
+            None => self.material_index.get(&corners).copied(),
+        }
+    }
+
+    /// Index in `rules` of the rule for a blob mask, `None` if the rule set doesn't cover it
+    pub(crate) fn blob_rule_index(&self, mask: u8) -> Option<usize> {
+        self.blob_index.get(&mask).copied()
+    }
+
+    /// The rule `rule_index` pointed to, or the default rule for `None`
+    pub(crate) fn rule_or_default(&self, index: Option<usize>) -> Option<&TileRule> {
+        match index {
+            Some(i) => self.rules.get(i),
+            None => self.default_rule.as_ref(),
+        }
+    }
+
+    /// Same as `try_tile_by_rules`, but panics if the neighbor combination has no rule
+    pub fn tile_by_rules(&self, neighbors: [bool; 4]) -> &TileRule {
+        if let Err(e) = self.check_loaded() {
+            panic!("Tried to use the tile rules: {}", e);
+        }
+
+        match self.try_tile_by_rules(neighbors) {
+            Some(rule) => rule,
+            None => panic!("Neighbors value {:?} not found in the rules", neighbors),
         }
+    }
 
-        if self.yaml_file.is_none() {
-            error!("Tried to use the tile rules without providing a yaml file");
-            std::process::exit(1);
+    /// Checks the rules can be drawn: something was loaded, and there is an atlas to draw
+    /// from (unless they were loaded headless). Where the rules came from doesn't matter.
+    pub fn check_loaded(&self) -> Result<(), TileError> {
+        if self.rules.is_empty() {
+            return Err(TileError::NotLoaded);
         }
 
-        if self.sprite_atlas.is_none() {
-            error!("Tried to use the tile rules without providing a sprite atlas");
-            std::process::exit(1);
+        if self.uses_main_atlas && self.sprite_atlas.is_none() && !self.headless {
+            return Err(TileError::MissingAtlas);
         }
+        Ok(())
+    }
+}
+
+impl Default for TileRules {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
+/// Called with the world coordinate and new value of a changed tile, see `TileMap::on_change`
+pub type ChangeCallback = Box<dyn FnMut(i32, i32, bool)>;
+
+/// What a map holds in memory, see `TileMap::memory_stats`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryStats {
+    pub chunks: usize,
+    pub cells: usize,      // Tiles of every chunk, overlapping ones counted for each
+    pub data_bytes: usize, // Taken by the tile data of the chunks
+    pub textures: usize,   // Loaded by the rules, 0 without the render feature
+}
+
 pub struct TileMap {
     pub rules: TileRules,
     pub chunks: Vec<Chunk>,
+    scale: f32,
+    time: f32,
+    auto_expand: bool,
+    chunk_size: (i32, i32),
+    edge_mode: EdgeMode,
+    grid_index: HashMap<(i32, i32), usize>, // Grid cell to index in chunks
+    loose_chunks: bool,                     // Whether some chunks are off the chunk_size grid
+    history: Option<History>,
+    wrap: Option<(i32, i32)>,
+    changed: Option<HashSet<(i32, i32)>>, // Tiles changed since take_dirty, when tracking
+    on_change: Option<ChangeCallback>,
+    dirty: bool,
+    batching: bool,
+    pending: Option<(i32, i32, i32, i32)>, // Region changed during a batch, max exclusive
+    rule_cache: bool,
+    draw_batching: bool,
+    seed: u64,
+    density_threshold: u8,
 }
 
 impl TileMap {
-    pub fn new(rules: TileRules) -> Self {
-        rules.check_loaded();
+    /// Makes an empty map drawn with `rules`, failing if they can't be drawn (see
+    /// `TileRules::check_loaded`)
+    pub fn new(rules: TileRules) -> Result<Self, TileError> {
+        rules.check_loaded()?;
 
-        Self {
+        Ok(Self {
             rules,
             chunks: vec![],
+            scale: 4.0,
+            time: 0.0,
+            auto_expand: false,
+            chunk_size: (16, 16),
+            edge_mode: EdgeMode::Empty,
+            grid_index: HashMap::new(),
+            loose_chunks: false,
+            history: None,
+            wrap: None,
+            changed: None,
+            on_change: None,
+            dirty: true,
+            batching: false,
+            pending: None,
+            rule_cache: true,
+            draw_batching: false,
+            seed: 0,
+            density_threshold: 128,
+        })
+    }
+
+    /// Sets the seed sprite variants are picked with, changing it reshuffles them. The pick
+    /// hashes the world cell and the seed with SplitMix64 rather than `DefaultHasher`, so
+    /// the same map and seed look the same on every run and platform.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// When enabled, `draw` collects every visible cell first and draws them sorted by
+    /// texture and sprite, so the GPU switches textures as rarely as possible. Mostly helps
+    /// without a shared atlas, where every sprite has its own texture. Off by default.
+    pub fn set_batching(&mut self, enabled: bool) {
+        self.draw_batching = enabled;
+    }
+
+    /// Whether `draw` keeps the rule of every drawn cell per chunk instead of sampling the
+    /// corners and looking the rule up every frame, on by default. A chunk's cache is built
+    /// on its first draw and dropped when an edit touches it or one of its border cells.
+    /// Reload the rules through `reload_rules` so the caches get dropped.
+    pub fn set_rule_cache(&mut self, enabled: bool) {
+        self.rule_cache = enabled;
+        if !enabled {
+            for chunk in self.chunks.iter() {
+                chunk.rule_cache.replace(None);
+            }
         }
     }
 
-    pub fn get(&self, x: i32, y: i32) -> bool {
-        for chunk in self.chunks.iter() {
-            if x >= chunk.x
-                && x < chunk.x + chunk.size_x
-                && y >= chunk.y
-                && y < chunk.y + chunk.size_y
-            {
-                return chunk.get(x - chunk.x, y - chunk.y);
+    /// Whether anything that changes what `draw` shows happened since the last `clear_dirty`:
+    /// a tile edit, a tint, an added, removed, shown or hidden chunk, a new edge mode or
+    /// reloaded rules. Starts out dirty. Writing to `chunks` directly bypasses it, call
+    /// `mark_dirty` after doing so.
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    pub fn clear_dirty(&mut self) {
+        self.dirty = false;
+    }
+
+    pub fn mark_dirty(&mut self) {
+        self.invalidate_all();
+    }
+
+    /// Runs `f` with invalidation suspended, then invalidates everything it changed at once.
+    /// Edits inside still apply right away, only the dirty flag (and anything derived from
+    /// it) is updated a single time at the end. Outside of a stroke the edits are recorded
+    /// in the history as one, so a single `undo` reverts the whole batch. Nested batches join
+    /// the outer one.
+    pub fn batch(&mut self, f: impl FnOnce(&mut Self)) {
+        if self.batching {
+            f(self);
+            return;
+        }
+
+        let stroke = self
+            .history
+            .as_ref()
+            .is_some_and(|history| !history.in_stroke());
+        if stroke {
+            self.begin_stroke();
+        }
+
+        self.batching = true;
+        f(self);
+        self.batching = false;
+
+        if stroke {
+            self.end_stroke();
+        }
+
+        if let Some((min_x, min_y, max_x, max_y)) = self.pending.take() {
+            self.invalidate(min_x, min_y, max_x - min_x, max_y - min_y);
+        }
+    }
+
+    /// Marks the `w` by `h` rectangle of tiles starting at `(x, y)` as changed, or only
+    /// remembers it while batching
+    fn invalidate(&mut self, x: i32, y: i32, w: i32, h: i32) {
+        if w <= 0 || h <= 0 {
+            return;
+        }
+
+        if self.batching {
+            let (min_x, min_y, max_x, max_y) = self.pending.unwrap_or((x, y, x + w, y + h));
+            self.pending = Some((
+                i32::min(min_x, x),
+                i32::min(min_y, y),
+                i32::max(max_x, x + w),
+                i32::max(max_y, y + h),
+            ));
+            return;
+        }
+
+        self.dirty = true;
+
+        // A tile is a corner of the drawn cells up and left of it as well (and a neighbor of
+        // every tile around it in blob mode), while wrapping and clamping make edge tiles
+        // show up anywhere outside of the chunks, and a wrapping map on the other side
+        let everywhere =
+            matches!(self.edge_mode, EdgeMode::Wrap | EdgeMode::Clamp) || self.wrap.is_some();
+
+        // With every chunk alone in its grid cell the touched ones are found through the
+        // index, unless the rectangle spans more cells than there are chunks
+        let (min_x, min_y) = self.grid_key(x - 1, y - 1);
+        let (max_x, max_y) = self.grid_key(x + w + 1, y + h + 1);
+        let cells = (max_x - min_x + 1) as usize * (max_y - min_y + 1) as usize;
+        if !everywhere
+            && !self.loose_chunks
+            && self.grid_index.len() == self.chunks.len()
+            && cells <= self.chunks.len()
+        {
+            for cell_y in min_y..=max_y {
+                for cell_x in min_x..=max_x {
+                    if let Some(&i) = self.grid_index.get(&(cell_x, cell_y)) {
+                        self.chunks[i].rule_cache.replace(None);
+                    }
+                }
             }
+            return;
         }
 
-        return false;
+        for chunk in self.chunks.iter() {
+            // Drawn cells of a chunk start one before its origin and end on its last tile
+            let touched = x < chunk.x + chunk.size_x + 2
+                && chunk.x < x + w + 2
+                && y < chunk.y + chunk.size_y + 2
+                && chunk.y < y + h + 2;
+            if everywhere || touched {
+                chunk.rule_cache.replace(None);
+            }
+        }
     }
 
-    pub fn set(&mut self, x: i32, y: i32, value: bool) {
-        for chunk in self.chunks.iter_mut() {
-            if x >= chunk.x
-                && x < chunk.x + chunk.size_x
-                && y >= chunk.y
-                && y < chunk.y + chunk.size_y
-            {
-                chunk.set(x - chunk.x, y - chunk.y, value);
-                return;
+    fn invalidate_all(&mut self) {
+        match self.extent() {
+            Some((min_x, min_y, max_x, max_y)) => {
+                self.invalidate(min_x, min_y, max_x - min_x, max_y - min_y)
             }
+            // Nothing left to draw still changes what was drawn
+            None => self.dirty = true,
         }
     }
 
-    pub fn add_chunk(&mut self, x: i32, y: i32, size_x: i32, size_y: i32) {
-        let chunk = Chunk::new(
-            x,
-            y,
-            size_x,
-            size_y,
-            vec![vec![false; size_x as usize]; size_y as usize],
-        );
-        self.chunks.push(chunk);
+    /// Starts recording tile edits for `undo` and `redo`, keeping the last `depth` strokes.
+    /// Tints and added or removed chunks aren't recorded.
+    pub fn enable_history(&mut self, depth: usize) {
+        self.history = Some(History::new(depth));
     }
 
-    pub fn draw(&self, d: &mut RaylibDrawHandle) {
-        for chunk in self.chunks.iter() {
-            // -1 Cause we want to draw the left and top edge tiles not present in any chunks
-            for y in -1..chunk.size_y {
-                for x in -1..chunk.size_x {
-                    let neighbors = [
-                        chunk.get(x, y),
-                        self.get(x + 1 + chunk.x, y + chunk.y),
-                        self.get(x + chunk.x, y + 1 + chunk.y),
-                        self.get(x + 1 + chunk.x, y + 1 + chunk.y),
-                    ];
-
-                    let sprite_rule = self.rules.tile_by_rules(neighbors);
-
-                    d.draw_texture_pro(
-                        &sprite_rule.sprite,
-                        Rectangle::new(0.0, 0.0, sprite_rule.size as f32, sprite_rule.size as f32),
-                        Rectangle::new(
-                            (chunk.x + x) as f32 * sprite_rule.size as f32 * 4.0
-                                + sprite_rule.size as f32 * 4.0 / 2.0,
-                            (chunk.y + y) as f32 * sprite_rule.size as f32 * 4.0
-                                + sprite_rule.size as f32 * 4.0 / 2.0,
-                            sprite_rule.size as f32 * 4.0,
-                            sprite_rule.size as f32 * 4.0,
-                        ),
-                        Vector2::new(0.0, 0.0),
-                        0.0,
-                        Color::WHITE,
-                    );
+    pub fn disable_history(&mut self) {
+        self.history = None;
+    }
+
+    pub fn history(&self) -> Option<&History> {
+        self.history.as_ref()
+    }
+
+    /// Groups the following edits into a single undo step until `end_stroke`,
+    /// like every tile painted while a mouse button is held
+    pub fn begin_stroke(&mut self) {
+        if let Some(history) = self.history.as_mut() {
+            history.begin_stroke();
+        }
+    }
+
+    pub fn end_stroke(&mut self) {
+        if let Some(history) = self.history.as_mut() {
+            history.end_stroke();
+        }
+    }
+
+    /// Reverts the last stroke, returning false if there was nothing to undo
+    pub fn undo(&mut self) -> bool {
+        // Take the history out so the reverted edits aren't recorded again
+        let Some(mut history) = self.history.take() else {
+            return false;
+        };
+
+        let stroke = history.pop_undo();
+        if let Some(ref stroke) = stroke {
+            for change in stroke.iter().rev() {
+                self.write_tile(change.x, change.y, change.old);
+            }
+        }
+
+        let undone = stroke.is_some();
+        if let Some(stroke) = stroke {
+            history.push_redo(stroke);
+        }
+        self.history = Some(history);
+        undone
+    }
+
+    /// Applies the last undone stroke again, returning false if there was nothing to redo
+    pub fn redo(&mut self) -> bool {
+        let Some(mut history) = self.history.take() else {
+            return false;
+        };
+
+        let stroke = history.pop_redo();
+        if let Some(ref stroke) = stroke {
+            for change in stroke.iter() {
+                self.write_tile(change.x, change.y, change.new);
+            }
+        }
+
+        let redone = stroke.is_some();
+        if let Some(stroke) = stroke {
+            history.push_redone(stroke);
+        }
+        self.history = Some(history);
+        redone
+    }
+
+    /// Starts or stops collecting the coordinates of changed tiles for `take_dirty`, off by
+    /// default. Stopping drops what was collected.
+    pub fn enable_change_tracking(&mut self, enabled: bool) {
+        if enabled {
+            self.changed.get_or_insert_with(HashSet::new);
+        } else {
+            self.changed = None;
+        }
+    }
+
+    /// Returns the world coordinates of every tile whose value changed since the last call,
+    /// and starts over. Every tile edit counts, undo and redo included, but adding, removing
+    /// or reshaping chunks doesn't. Always empty without `enable_change_tracking`.
+    pub fn take_dirty(&mut self) -> HashSet<(i32, i32)> {
+        match self.changed.as_mut() {
+            Some(changed) => std::mem::take(changed),
+            None => HashSet::new(),
+        }
+    }
+
+    /// Calls `f` with the world coordinate and new value of every tile an edit changes,
+    /// replacing the previous callback. Writing the value a tile already holds doesn't call
+    /// it, a tile changing material does. Single tile edits call it right away, bulk edits
+    /// like `fill_rect` or `ca_step` once per changed tile after they wrote every tile,
+    /// going row by row through each chunk.
+    pub fn on_change(&mut self, f: ChangeCallback) {
+        self.on_change = Some(f);
+    }
+
+    pub fn clear_on_change(&mut self) {
+        self.on_change = None;
+    }
+
+    /// Called once for every tile an edit changed
+    fn report_change(&mut self, x: i32, y: i32, old: u8, new: u8) {
+        if let Some(history) = self.history.as_mut() {
+            history.record(TileChange { x, y, old, new });
+        }
+        if let Some(changed) = self.changed.as_mut() {
+            changed.insert((x, y));
+        }
+        if let Some(on_change) = self.on_change.as_mut() {
+            on_change(x, y, new != 0);
+        }
+    }
+
+    fn tile_changed(&mut self, x: i32, y: i32, old: u8, new: u8) {
+        self.report_change(x, y, old, new);
+        self.invalidate(x, y, 1, 1);
+    }
+
+    /// Sets a tile of an existing chunk, reporting the change. Every single tile edit goes
+    /// through here. Returns false if no chunk holds the coordinate.
+    fn write_tile(&mut self, x: i32, y: i32, tile: u8) -> bool {
+        let (x, y) = self.wrap_tile(x, y);
+        let Some(chunk) = self.get_chunk_at_mut(x, y) else {
+            return false;
+        };
+
+        let (local_x, local_y) = (x - chunk.x, y - chunk.y);
+        chunk.densities.remove(&(local_x, local_y));
+        let old = chunk.get_tile(local_x, local_y);
+        if old != tile {
+            chunk.set_tile(local_x, local_y, tile);
+            self.tile_changed(x, y, old, tile);
+        }
+        true
+    }
+
+    /// Whether chunk `i` holds the tile at the world coordinate, where chunks overlap only
+    /// the one on top does
+    fn owns_tile(&self, i: usize, x: i32, y: i32) -> bool {
+        self.chunk_index_at(x, y) == Some(i)
+    }
+
+    /// Sets every tile a bulk edit collected as `(chunk, local x, local y, old)` to `f(old)`,
+    /// dropping their densities, then reports them once all are written and invalidates
+    /// them in a single batch
+    fn write_changes(&mut self, changes: Vec<(usize, i32, i32, u8)>, f: impl Fn(u8) -> u8) {
+        let mut written: Vec<(i32, i32, u8, u8)> = Vec::with_capacity(changes.len());
+        for (i, x, y, old) in changes {
+            let chunk = &mut self.chunks[i];
+            chunk.densities.remove(&(x, y));
+            let new = f(old);
+            if new != old {
+                chunk.set_tile(x, y, new);
+                written.push((chunk.x + x, chunk.y + y, old, new));
+            }
+        }
+
+        self.batch(|map| {
+            for (x, y, old, new) in written {
+                map.tile_changed(x, y, old, new);
+            }
+        });
+    }
+
+    /// Sets what reading outside of every chunk returns. `Wrap` and `Clamp` work on
+    /// the bounding box of all chunks.
+    pub fn set_edge_mode(&mut self, edge_mode: EdgeMode) {
+        self.edge_mode = edge_mode;
+        self.invalidate_all();
+    }
+
+    pub fn edge_mode(&self) -> EdgeMode {
+        self.edge_mode
+    }
+
+    /// Makes the world repeat every `(w, h)` tiles, for wrap around arenas and endless
+    /// backgrounds: reading and writing tile `(x, y)` goes to `(x mod w, y mod h)`, so
+    /// the chunks only need to cover `(0, 0)` to `(w, h)`. The cells on the seam see the
+    /// tiles on the other side, and drawing repeats the map over the whole view. `None`
+    /// or a size that isn't positive turns it off.
+    pub fn set_wrap(&mut self, wrap: Option<(i32, i32)>) {
+        self.wrap = wrap.filter(|&(w, h)| w > 0 && h > 0);
+        self.invalidate_all();
+    }
+
+    pub fn wrap(&self) -> Option<(i32, i32)> {
+        self.wrap
+    }
+
+    /// The coordinate a world coordinate wraps to, see `set_wrap`
+    fn wrap_tile(&self, x: i32, y: i32) -> (i32, i32) {
+        match self.wrap {
+            Some((w, h)) => (x.rem_euclid(w), y.rem_euclid(h)),
+            None => (x, y),
+        }
+    }
+
+    /// Returns the bounding box of all chunks as `(min_x, min_y, max_x, max_y)`, max exclusive
+    pub fn extent(&self) -> Option<(i32, i32, i32, i32)> {
+        self.chunks.iter().fold(None, |extent, chunk| {
+            let (min_x, min_y, max_x, max_y) = extent.unwrap_or((
+                chunk.x,
+                chunk.y,
+                chunk.x + chunk.size_x,
+                chunk.y + chunk.size_y,
+            ));
+            Some((
+                i32::min(min_x, chunk.x),
+                i32::min(min_y, chunk.y),
+                i32::max(max_x, chunk.x + chunk.size_x),
+                i32::max(max_y, chunk.y + chunk.size_y),
+            ))
+        })
+    }
+
+    /// Counts what the map holds, to tell when cropping or compacting it is worth it. Only
+    /// sums the chunk sizes, so it's cheap enough to show every frame.
+    pub fn memory_stats(&self) -> MemoryStats {
+        #[cfg(feature = "render")]
+        let textures = self.rules.textures.len();
+        #[cfg(not(feature = "render"))]
+        let textures = 0;
+
+        MemoryStats {
+            chunks: self.chunks.len(),
+            cells: self
+                .chunks
+                .iter()
+                .map(|chunk| (chunk.size_x * chunk.size_y) as usize)
+                .sum(),
+            data_bytes: self
+                .chunks
+                .iter()
+                .map(|chunk| chunk.data.capacity() * std::mem::size_of::<u8>())
+                .sum(),
+            textures,
+        }
+    }
+
+    /// Returns the tightest box holding every filled tile as `(min_x, min_y, max_x, max_y)`,
+    /// max exclusive like `extent`, or `None` if no tile is filled
+    pub fn active_bounds(&self) -> Option<(i32, i32, i32, i32)> {
+        let mut bounds: Option<(i32, i32, i32, i32)> = None;
+        for chunk in self.chunks.iter() {
+            for (i, &tile) in chunk.data.iter().enumerate() {
+                if tile == 0 {
+                    continue;
+                }
+
+                let x = chunk.x + i as i32 % chunk.size_x;
+                let y = chunk.y + i as i32 / chunk.size_x;
+                bounds = Some(match bounds {
+                    None => (x, y, x + 1, y + 1),
+                    Some((min_x, min_y, max_x, max_y)) => (
+                        i32::min(min_x, x),
+                        i32::min(min_y, y),
+                        i32::max(max_x, x + 1),
+                        i32::max(max_y, y + 1),
+                    ),
+                });
+            }
+        }
+        bounds
+    }
+
+    /// Replaces every chunk with a single one covering `active_bounds`, dropping the empty
+    /// space around the filled tiles. Tiles, tints and densities keep their world
+    /// coordinates. A map without filled tiles ends up with no chunks.
+    pub fn crop_to_active(&mut self) {
+        let Some((min_x, min_y, max_x, max_y)) = self.active_bounds() else {
+            self.chunks.clear();
+            self.reindex();
+            return;
+        };
+
+        let (size_x, size_y) = (max_x - min_x, max_y - min_y);
+        let mut data = Vec::with_capacity((size_x * size_y) as usize);
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                data.push(match self.get_chunk_at(x, y) {
+                    Some(chunk) => chunk.get_tile(x - chunk.x, y - chunk.y),
+                    None => 0,
+                });
+            }
+        }
+
+        let mut cropped = Chunk::from_flat(min_x, min_y, size_x, size_y, data);
+        for (i, chunk) in self.chunks.iter().enumerate() {
+            for (&(x, y), &tint) in chunk.tints.iter() {
+                cropped.set_tint(chunk.x + x - min_x, chunk.y + y - min_y, tint);
+            }
+            for (&(x, y), &density) in chunk.densities.iter() {
+                let (x, y) = (chunk.x + x, chunk.y + y);
+                // Where chunks overlap only the one on top holds the tile
+                if (min_x..max_x).contains(&x)
+                    && (min_y..max_y).contains(&y)
+                    && self.chunk_index_at(x, y) == Some(i)
+                {
+                    cropped.densities.insert((x - min_x, y - min_y), density);
+                }
+            }
+        }
+
+        self.chunks = vec![cropped];
+        self.reindex();
+    }
+
+    /// Rotates the filled part of the map a quarter turn clockwise. The map is cropped to
+    /// `active_bounds` first and the rotated chunk keeps the top left corner of the bounds.
+    /// Only the tiles move, the rules pick the sprites again for the new layout.
+    pub fn rotate_90(&mut self) {
+        self.remap(true, |x, y, _, size_y| (size_y - 1 - y, x));
+    }
+
+    /// Mirrors the filled part of the map left to right, cropping it like `rotate_90`
+    pub fn flip_horizontal(&mut self) {
+        self.remap(false, |x, y, size_x, _| (size_x - 1 - x, y));
+    }
+
+    /// Mirrors the filled part of the map top to bottom, cropping it like `rotate_90`
+    pub fn flip_vertical(&mut self) {
+        self.remap(false, |x, y, _, size_y| (x, size_y - 1 - y));
+    }
+
+    /// Crops the map to its active bounds and moves every tile, tint and density of the chunk
+    /// left to `f(x, y, size_x, size_y)`, swapping the chunk's width and height with `swap`
+    fn remap(&mut self, swap: bool, f: impl Fn(i32, i32, i32, i32) -> (i32, i32)) {
+        self.crop_to_active();
+        let Some(chunk) = self.chunks.first() else {
+            return;
+        };
+
+        let (size_x, size_y) = (chunk.size_x, chunk.size_y);
+        let (new_x, new_y) = if swap {
+            (size_y, size_x)
+        } else {
+            (size_x, size_y)
+        };
+        let mut data = vec![0; (new_x * new_y) as usize];
+        for y in 0..size_y {
+            for x in 0..size_x {
+                let (to_x, to_y) = f(x, y, size_x, size_y);
+                data[(to_y * new_x + to_x) as usize] = chunk.get_tile(x, y);
+            }
+        }
+
+        let mut remapped = Chunk::from_flat(chunk.x, chunk.y, new_x, new_y, data);
+        for (&(x, y), &tint) in chunk.tints.iter() {
+            let (to_x, to_y) = f(x, y, size_x, size_y);
+            remapped.set_tint(to_x, to_y, tint);
+        }
+        for (&(x, y), &density) in chunk.densities.iter() {
+            remapped.densities.insert(f(x, y, size_x, size_y), density);
+        }
+
+        self.chunks = vec![remapped];
+        self.reindex();
+    }
+
+    /// When enabled, setting a tile outside of every chunk creates the chunk holding it
+    /// instead of ignoring the write. Chunks are snapped to a grid of `chunk_size`.
+    pub fn set_auto_expand(&mut self, enabled: bool) {
+        self.auto_expand = enabled;
+    }
+
+    /// Size of the chunks created by auto expansion, 16x16 by default. Chunks of this size
+    /// placed on its grid are found through a hash index, any other chunk by a linear scan.
+    pub fn set_chunk_size(&mut self, size_x: i32, size_y: i32) {
+        assert!(size_x > 0 && size_y > 0, "Chunk size must be positive");
+        self.chunk_size = (size_x, size_y);
+        self.reindex();
+    }
+
+    pub fn chunk_size(&self) -> (i32, i32) {
+        self.chunk_size
+    }
+
+    /// Advances the animation clock shared by every animated rule
+    pub fn update(&mut self, dt: f32) {
+        self.time += dt;
+    }
+
+    /// Sets the factor tiles are upscaled by when drawn, 4.0 by default
+    pub fn with_scale(mut self, scale: f32) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    pub fn set_scale(&mut self, scale: f32) {
+        self.scale = scale;
+    }
+
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// Width and height of a tile on screen, the rule size times the scale
+    pub fn scaled_tile_size(&self) -> Vector2 {
+        let (size_x, size_y) = self.rules.tile_size();
+        Vector2::new(size_x as f32 * self.scale, size_y as f32 * self.scale)
+    }
+
+    /// Returns the tile under a screen position
+    pub fn screen_to_tile(&self, screen: Vector2) -> (i32, i32) {
+        let size = self.scaled_tile_size();
+        (
+            f32::floor(screen.x / size.x) as i32,
+            f32::floor(screen.y / size.y) as i32,
+        )
+    }
+
+    /// Returns the screen position of the tile's center. Dual grid sprites are drawn half a
+    /// tile off the grid, so this is also the corner where the four sprites showing it meet.
+    pub fn tile_to_screen(&self, x: i32, y: i32) -> Vector2 {
+        let size = self.scaled_tile_size();
+        Vector2::new(
+            x as f32 * size.x + size.x / 2.0,
+            y as f32 * size.y + size.y / 2.0,
+        )
+    }
+
+    /// Whether the tile under a screen position is filled, see `screen_to_tile`. With a camera,
+    /// pass the position through `get_screen_to_world2D` first.
+    pub fn get_at_screen(&self, screen: Vector2) -> bool {
+        let (x, y) = self.screen_to_tile(screen);
+        self.get(x, y)
+    }
+
+    /// Sets the tile under a screen position, like `get_at_screen` finds it
+    pub fn set_at_screen(&mut self, screen: Vector2, value: bool) {
+        let (x, y) = self.screen_to_tile(screen);
+        self.set(x, y, value);
+    }
+
+    /// Sets every tile within `brush_radius` tiles of the one under a world position, a disc
+    /// of them like `paint` with `BrushShape::Circle`. A radius of 0 sets only that tile like
+    /// `set_at_screen`. With a camera, pass the position through `get_screen_to_world2D`.
+    pub fn paint_world(&mut self, world: Vector2, value: bool, brush_radius: i32) {
+        let (x, y) = self.screen_to_tile(world);
+        self.paint(x, y, value, brush_radius, BrushShape::Circle);
+    }
+
+    /// Returns the screen rectangle the tile covers at the current scale
+    pub fn tile_rect(&self, x: i32, y: i32) -> Rectangle {
+        let size = self.scaled_tile_size();
+        Rectangle::new(x as f32 * size.x, y as f32 * size.y, size.x, size.y)
+    }
+
+    /// Returns the screen rectangle `draw` stretches the sprite of cell `(x, y)` over. Dual
+    /// grid sprites sit between tiles `(x, y)` and `(x + 1, y + 1)`, so it's `tile_rect`
+    /// moved half a tile right and down, while blob sprites cover their own tile.
+    pub fn cell_rect(&self, x: i32, y: i32) -> Rectangle {
+        let mut rect = self.tile_rect(x, y);
+        if self.rules.mode() == RuleMode::DualGrid {
+            rect.x += rect.width / 2.0;
+            rect.y += rect.height / 2.0;
+        }
+        rect
+    }
+
+    /// Returns the material id at the world coordinate, 0 being empty
+    pub fn get_tile(&self, x: i32, y: i32) -> u8 {
+        let (x, y) = self.wrap_tile(x, y);
+        if let Some(chunk) = self.get_chunk_at(x, y) {
+            return chunk.get_tile(x - chunk.x, y - chunk.y);
+        }
+
+        match self.edge_mode {
+            EdgeMode::Empty => 0,
+            EdgeMode::Filled => 1,
+            EdgeMode::Wrap | EdgeMode::Clamp => {
+                let Some((min_x, min_y, max_x, max_y)) = self.extent() else {
+                    return 0;
+                };
+                if min_x >= max_x || min_y >= max_y {
+                    return 0;
+                }
+
+                let (x, y) = if self.edge_mode == EdgeMode::Wrap {
+                    (
+                        min_x + (x - min_x).rem_euclid(max_x - min_x),
+                        min_y + (y - min_y).rem_euclid(max_y - min_y),
+                    )
+                } else {
+                    (x.clamp(min_x, max_x - 1), y.clamp(min_y, max_y - 1))
+                };
+
+                // Gaps between chunks are still empty
+                match self.get_chunk_at(x, y) {
+                    Some(chunk) => chunk.get_tile(x - chunk.x, y - chunk.y),
+                    None => 0,
+                }
+            }
+        }
+    }
+
+    pub fn set_tile(&mut self, x: i32, y: i32, tile: u8) {
+        let (x, y) = self.wrap_tile(x, y);
+
+        // Clearing a missing tile changes nothing, so only allocate for actual content
+        if self.auto_expand && tile != 0 && self.get_chunk_at(x, y).is_none() {
+            let (size_x, size_y) = self.chunk_size;
+            self.add_chunk(
+                x.div_euclid(size_x) * size_x,
+                y.div_euclid(size_y) * size_y,
+                size_x,
+                size_y,
+            );
+        }
+
+        self.write_tile(x, y, tile);
+    }
+
+    /// Whether the tile holds any material
+    pub fn get(&self, x: i32, y: i32) -> bool {
+        self.get_tile(x, y) != 0
+    }
+
+    /// Sets the tile to material 1 when `value` is true, or clears it
+    pub fn set(&mut self, x: i32, y: i32, value: bool) {
+        self.set_tile(x, y, value as u8);
+    }
+
+    pub fn get_tint(&self, x: i32, y: i32) -> Option<Color> {
+        let chunk = self.get_chunk_at(x, y)?;
+        chunk.get_tint(x - chunk.x, y - chunk.y)
+    }
+
+    /// Tints the tile. Every drawn sprite samples four tiles, so it takes the tint of its
+    /// top left one: the sprite drawn half a tile right and down of this tile.
+    pub fn set_tint(&mut self, x: i32, y: i32, tint: Color) {
+        if let Some(chunk) = self.get_chunk_at_mut(x, y) {
+            chunk.set_tint(x - chunk.x, y - chunk.y, tint);
+            self.dirty = true;
+        }
+    }
+
+    pub fn clear_tint(&mut self, x: i32, y: i32) {
+        if let Some(chunk) = self.get_chunk_at_mut(x, y) {
+            chunk.clear_tint(x - chunk.x, y - chunk.y);
+            self.dirty = true;
+        }
+    }
+
+    /// Sets the density of the tile, a height or amount of material kept next to it for
+    /// terrain that is smoothed or eroded before being drawn. The tile is filled while the
+    /// density is at least the threshold (see `set_density_threshold`), keeping its material
+    /// if it already had one, and cleared below it. Tiles outside of every chunk are skipped.
+    pub fn set_density(&mut self, x: i32, y: i32, density: u8) {
+        let (x, y) = self.wrap_tile(x, y);
+        let Some(chunk) = self.get_chunk_at(x, y) else {
+            return;
+        };
+
+        let old = chunk.get_tile(x - chunk.x, y - chunk.y);
+        let tile = if density >= self.density_threshold {
+            old.max(1)
+        } else {
+            0
+        };
+        self.write_tile(x, y, tile);
+
+        // Writing the tile dropped its old density
+        if let Some(chunk) = self.get_chunk_at_mut(x, y) {
+            chunk.densities.insert((x - chunk.x, y - chunk.y), density);
+        }
+    }
+
+    /// The density of the tile set with `set_density`. Tiles without one, or written since
+    /// by any other edit, read as 255 when filled and 0 when empty.
+    pub fn get_density(&self, x: i32, y: i32) -> u8 {
+        let (x, y) = self.wrap_tile(x, y);
+        let filled = self.get(x, y);
+        let density = self
+            .get_chunk_at(x, y)
+            .and_then(|chunk| chunk.densities.get(&(x - chunk.x, y - chunk.y)).copied());
+        match density {
+            Some(density) if (density >= self.density_threshold) == filled => density,
+            _ if filled => 255,
+            _ => 0,
+        }
+    }
+
+    /// Sets the density a tile is filled from, 128 by default, filling or clearing every
+    /// tile with a density so they match it
+    pub fn set_density_threshold(&mut self, threshold: u8) {
+        let mut densities: Vec<(i32, i32, u8)> = vec![];
+        for (i, chunk) in self.chunks.iter().enumerate() {
+            for (&(x, y), &density) in chunk.densities.iter() {
+                let (x, y) = (chunk.x + x, chunk.y + y);
+                // Where chunks overlap only the one on top holds the tile
+                if self.chunk_index_at(x, y) == Some(i) {
+                    densities.push((x, y, density));
+                }
+            }
+        }
+
+        self.density_threshold = threshold;
+        self.batch(|map| {
+            for (x, y, density) in densities {
+                map.set_density(x, y, density);
+            }
+        });
+    }
+
+    pub fn density_threshold(&self) -> u8 {
+        self.density_threshold
+    }
+
+    /// Clears a filled tile or fills an empty one, returning the new value,
+    /// or `None` if no chunk holds the coordinate
+    pub fn toggle(&mut self, x: i32, y: i32) -> Option<bool> {
+        let (x, y) = self.wrap_tile(x, y);
+        let chunk = self.get_chunk_at(x, y)?;
+        let value = !chunk.get(x - chunk.x, y - chunk.y);
+        self.write_tile(x, y, value as u8);
+        Some(value)
+    }
+
+    /// Sets every tile in the `w` by `h` rectangle starting at `(x, y)`,
+    /// tiles outside of every chunk are skipped
+    pub fn fill_rect(&mut self, x: i32, y: i32, w: i32, h: i32, value: bool) {
+        self.fill_rect_tile(x, y, w, h, value as u8);
+    }
+
+    pub fn fill_rect_tile(&mut self, x: i32, y: i32, w: i32, h: i32, tile: u8) {
+        let mut changes: Vec<(usize, i32, i32, u8)> = vec![];
+        for (i, chunk) in self.chunks.iter().enumerate() {
+            // Clip the rectangle to the chunk, in chunk-local coordinates
+            let start_x = i32::max(x, chunk.x) - chunk.x;
+            let start_y = i32::max(y, chunk.y) - chunk.y;
+            let end_x = i32::min(x + w, chunk.x + chunk.size_x) - chunk.x;
+            let end_y = i32::min(y + h, chunk.y + chunk.size_y) - chunk.y;
+
+            for cy in start_y..end_y {
+                for cx in start_x..end_x {
+                    if self.owns_tile(i, chunk.x + cx, chunk.y + cy) {
+                        changes.push((i, cx, cy, chunk.get_tile(cx, cy)));
+                    }
+                }
+            }
+        }
+
+        self.write_changes(changes, |_| tile);
+    }
+
+    /// Sets every tile of every chunk
+    pub fn fill_all(&mut self, value: bool) {
+        let chunks: Vec<(i32, i32, i32, i32)> = self
+            .chunks
+            .iter()
+            .map(|chunk| (chunk.x, chunk.y, chunk.size_x, chunk.size_y))
+            .collect();
+        self.batch(|map| {
+            for (x, y, w, h) in chunks {
+                map.fill_rect(x, y, w, h, value);
+            }
+        });
+    }
+
+    /// Fills every tile of every chunk with a chance of `density`, clearing the others, as
+    /// noise to smooth out with `ca_step`. Each tile rolls from a hash of its coordinate and
+    /// `seed`, so the same seed always gives the same map.
+    pub fn random_fill(&mut self, density: f32, seed: u64) {
+        let chunks: Vec<(i32, i32, i32, i32)> = self
+            .chunks
+            .iter()
+            .map(|chunk| (chunk.x, chunk.y, chunk.size_x, chunk.size_y))
+            .collect();
+        self.batch(|map| {
+            for (x, y, w, h) in chunks {
+                map.fill_with(x, y, w, h, |x, y| cell_unit(x, y, seed) < density);
+            }
+        });
+    }
+
+    /// Moves the content of the chunks by `(dx, dy)` while the chunks stay in place, like for
+    /// centering a generated level. Tiles moved off every chunk are lost and the ones left
+    /// behind are cleared, except with `EdgeMode::Wrap` where they come back in on the other
+    /// side of the extent.
+    pub fn shift(&mut self, dx: i32, dy: i32) {
+        let wrap = self.edge_mode == EdgeMode::Wrap;
+        let moved: Vec<((i32, i32), u8)> = self
+            .iter_tiles()
+            .map(|((x, y), _)| {
+                let (from_x, from_y) = (x - dx, y - dy);
+                let tile = if wrap || self.chunk_index_at(from_x, from_y).is_some() {
+                    self.get_tile(from_x, from_y)
+                } else {
+                    0
+                };
+                ((x, y), tile)
+            })
+            .collect();
+
+        self.batch(|map| {
+            for ((x, y), tile) in moved {
+                map.write_tile(x, y, tile);
+            }
+        });
+    }
+
+    /// Clears every filled tile of every chunk and fills every empty one
+    pub fn invert(&mut self) {
+        let mut changes: Vec<(usize, i32, i32, u8)> = vec![];
+        for (i, chunk) in self.chunks.iter().enumerate() {
+            for y in 0..chunk.size_y {
+                for x in 0..chunk.size_x {
+                    if self.owns_tile(i, chunk.x + x, chunk.y + y) {
+                        changes.push((i, x, y, chunk.get_tile(x, y)));
+                    }
+                }
+            }
+        }
+
+        self.write_changes(changes, |old| (old == 0) as u8);
+    }
+
+    /// Sets every tile on the Bresenham line from `(x0, y0)` to `(x1, y1)`, both ends
+    /// included, like `set` does
+    pub fn set_line(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, value: bool) {
+        self.batch(|map| {
+            for (x, y) in line_tiles(x0, y0, x1, y1) {
+                map.set(x, y, value);
+            }
+        });
+    }
+
+    /// Sets every tile in the `w` by `h` rectangle starting at `(x, y)` to `f(x, y)`,
+    /// called with world coordinates. Tiles outside of every chunk are skipped, unless
+    /// auto expansion is on and `f` fills them.
+    pub fn fill_with<F: FnMut(i32, i32) -> bool>(
+        &mut self,
+        x: i32,
+        y: i32,
+        w: i32,
+        h: i32,
+        mut f: F,
+    ) {
+        self.batch(|map| {
+            for ty in y..y + h {
+                for tx in x..x + w {
+                    let value = f(tx, ty);
+                    map.set(tx, ty, value);
+                }
+            }
+        });
+    }
+
+    /// Returns the `w` by `h` block of tiles starting at `(x, y)` as rows, tiles outside of
+    /// every chunk following the edge mode
+    pub fn get_region(&self, x: i32, y: i32, w: i32, h: i32) -> Vec<Vec<bool>> {
+        (y..y + h)
+            .map(|ty| (x..x + w).map(|tx| self.get(tx, ty)).collect())
+            .collect()
+    }
+
+    /// Writes a block of rows like the ones `get_region` returns with its top left at
+    /// `(x, y)`, tiles outside of every chunk are skipped
+    pub fn set_region(&mut self, x: i32, y: i32, region: &[Vec<bool>]) {
+        self.batch(|map| {
+            for (dy, row) in region.iter().enumerate() {
+                for (dx, &value) in row.iter().enumerate() {
+                    map.write_tile(x + dx as i32, y + dy as i32, value as u8);
+                }
+            }
+        });
+    }
+
+    /// Copies the tiles of every chunk of `other` into this map, moved by the offset, for
+    /// composing rooms out of prefabs. Without `overwrite` only `other`'s filled tiles are
+    /// copied, so empty ones leave this map's tiles alone, with it they clear them too.
+    /// Tiles landing outside of this map's chunks are skipped, unless auto expand adds chunks
+    /// for the filled ones.
+    pub fn merge(&mut self, other: &TileMap, offset_x: i32, offset_y: i32, overwrite: bool) {
+        self.batch(|map| {
+            for ((x, y), _) in other.iter_tiles() {
+                let tile = other.get_tile(x, y);
+                if tile != 0 || overwrite {
+                    map.set_tile(x + offset_x, y + offset_y, tile);
+                }
+            }
+        });
+    }
+
+    /// Runs one cellular automaton step over every chunk, for cave generation. An empty tile
+    /// with at least `birth` filled tiles of its 8 neighbors gets filled, a filled one with
+    /// fewer than `survive` gets cleared. Every tile reads the state from before the step,
+    /// and neighbors outside of the chunks follow the edge mode.
+    pub fn ca_step(&mut self, birth: u8, survive: u8) {
+        let mut changes: Vec<(usize, i32, i32, u8)> = vec![];
+        for (i, chunk) in self.chunks.iter().enumerate() {
+            for y in 0..chunk.size_y {
+                for x in 0..chunk.size_x {
+                    let (wx, wy) = (chunk.x + x, chunk.y + y);
+                    if !self.owns_tile(i, wx, wy) {
+                        continue;
+                    }
+
+                    let mut filled = 0;
+                    for (dx, dy) in [
+                        (-1, -1),
+                        (0, -1),
+                        (1, -1),
+                        (-1, 0),
+                        (1, 0),
+                        (-1, 1),
+                        (0, 1),
+                        (1, 1),
+                    ] {
+                        if self.get(wx + dx, wy + dy) {
+                            filled += 1;
+                        }
+                    }
+
+                    let old = chunk.get_tile(x, y);
+                    if (old == 0 && filled >= birth) || (old != 0 && filled < survive) {
+                        changes.push((i, x, y, old));
+                    }
+                }
+            }
+        }
+
+        self.write_changes(changes, |old| (old == 0) as u8);
+    }
+
+    /// Sets the 4-connected area of tiles sharing the value at `(x, y)` to `value`,
+    /// spreading across touching chunks but never outside of them
+    pub fn flood_fill(&mut self, x: i32, y: i32, value: bool) {
+        self.flood_fill_tile(x, y, value as u8);
+    }
+
+    pub fn flood_fill_tile(&mut self, x: i32, y: i32, tile: u8) {
+        let target = self.get_tile(x, y);
+        if self.get_chunk_at(x, y).is_none() || target == tile {
+            return;
+        }
+
+        self.batch(|map| {
+            let mut stack = vec![(x, y)];
+            while let Some((x, y)) = stack.pop() {
+                match map.get_chunk_at(x, y) {
+                    Some(chunk) if chunk.get_tile(x - chunk.x, y - chunk.y) == target => {
+                        map.write_tile(x, y, tile);
+                    }
+                    _ => continue,
+                }
+
+                stack.push((x - 1, y));
+                stack.push((x + 1, y));
+                stack.push((x, y - 1));
+                stack.push((x, y + 1));
+            }
+        });
+    }
+
+    /// Returns the chunk containing the world coordinate
+    pub fn get_chunk_at(&self, x: i32, y: i32) -> Option<&Chunk> {
+        self.chunk_index_at(x, y).map(|i| &self.chunks[i])
+    }
+
+    pub fn get_chunk_at_mut(&mut self, x: i32, y: i32) -> Option<&mut Chunk> {
+        self.chunk_index_at(x, y).map(|i| &mut self.chunks[i])
+    }
+
+    /// Every chunk, in the order they were added
+    pub fn chunks(&self) -> impl Iterator<Item = &Chunk> {
+        self.chunks.iter()
+    }
+
+    /// Every tile of every chunk with its world coordinate, row by row through each chunk in
+    /// the order they were added. Where chunks overlap only the one on top yields the tile.
+    pub fn iter_tiles(&self) -> impl Iterator<Item = ((i32, i32), bool)> + '_ {
+        self.chunks.iter().enumerate().flat_map(move |(i, chunk)| {
+            (0..chunk.size_y)
+                .flat_map(move |y| (0..chunk.size_x).map(move |x| (x, y)))
+                .filter_map(move |(x, y)| {
+                    let (world_x, world_y) = (chunk.x + x, chunk.y + y);
+                    (self.chunk_index_at(world_x, world_y) == Some(i))
+                        .then(|| ((world_x, world_y), chunk.get(x, y)))
+                })
+        })
+    }
+
+    /// World coordinates of the filled tiles, in the order of `iter_tiles`
+    pub fn iter_active(&self) -> impl Iterator<Item = (i32, i32)> + '_ {
+        self.iter_tiles()
+            .filter_map(|(position, filled)| filled.then_some(position))
+    }
+
+    fn grid_key(&self, x: i32, y: i32) -> (i32, i32) {
+        (
+            x.div_euclid(self.chunk_size.0),
+            y.div_euclid(self.chunk_size.1),
+        )
+    }
+
+    /// Index of the chunk holding the coordinate. Where chunks overlap, the one with the
+    /// highest z wins and the last added one breaks ties, so reads and writes always agree.
+    fn chunk_index_at(&self, x: i32, y: i32) -> Option<usize> {
+        // Every chunk is on the grid and the index holds the winner of every cell
+        if !self.loose_chunks {
+            return self.grid_index.get(&self.grid_key(x, y)).copied();
+        }
+
+        self.chunks
+            .iter()
+            .enumerate()
+            .filter(|(_, chunk)| chunk.contains(x, y))
+            .max_by_key(|&(i, chunk)| (chunk.z, i))
+            .map(|(i, _)| i)
+    }
+
+    /// Rebuilds the grid index, needed whenever `chunks` changes
+    fn reindex(&mut self) {
+        self.grid_index.clear();
+        self.loose_chunks = false;
+        self.invalidate_all();
+
+        let (size_x, size_y) = self.chunk_size;
+        for (i, chunk) in self.chunks.iter().enumerate() {
+            let aligned = chunk.size_x == size_x
+                && chunk.size_y == size_y
+                && chunk.x.rem_euclid(size_x) == 0
+                && chunk.y.rem_euclid(size_y) == 0;
+            if !aligned {
+                self.loose_chunks = true;
+                continue;
+            }
+
+            // Later chunks come after, so they win ties
+            let key = (chunk.x.div_euclid(size_x), chunk.y.div_euclid(size_y));
+            match self.grid_index.get(&key) {
+                Some(&other) if self.chunks[other].z > chunk.z => {}
+                _ => {
+                    self.grid_index.insert(key, i);
+                }
+            }
+        }
+    }
+
+    /// Sets the z of the chunk whose origin is exactly `(x, y)`, returning false if there is
+    /// none. The highest z wins where chunks overlap.
+    pub fn set_chunk_z(&mut self, x: i32, y: i32, z: i32) -> bool {
+        let Some(chunk) = self
+            .chunks
+            .iter_mut()
+            .find(|chunk| chunk.x == x && chunk.y == y)
+        else {
+            return false;
+        };
+
+        chunk.z = z;
+        self.reindex();
+        true
+    }
+
+    /// Empties every tile of the chunk whose origin is exactly `(x, y)`, keeping the chunk and
+    /// its allocation around to be filled again. Returns false if there is none. The cleared
+    /// tiles are recorded like any other edit.
+    pub fn clear_chunk(&mut self, x: i32, y: i32) -> bool {
+        let Some(i) = self
+            .chunks
+            .iter()
+            .position(|chunk| chunk.x == x && chunk.y == y)
+        else {
+            return false;
+        };
+
+        let chunk = &self.chunks[i];
+        let mut changes: Vec<(usize, i32, i32, u8)> = vec![];
+        for cy in 0..chunk.size_y {
+            for cx in 0..chunk.size_x {
+                let old = chunk.get_tile(cx, cy);
+                if old != 0 && self.owns_tile(i, x + cx, y + cy) {
+                    changes.push((i, cx, cy, old));
                 }
             }
         }
+
+        self.write_changes(changes, |_| 0);
+        self.chunks[i].densities.clear();
+        true
+    }
+
+    /// Shows or hides the chunk whose origin is exactly `(x, y)`, returning false if there is
+    /// none. A hidden chunk isn't drawn at all but keeps its tiles, which still count for
+    /// collisions and for the cells of the chunks around it.
+    pub fn set_chunk_visible(&mut self, x: i32, y: i32, visible: bool) -> bool {
+        let Some(chunk) = self
+            .chunks
+            .iter_mut()
+            .find(|chunk| chunk.x == x && chunk.y == y)
+        else {
+            return false;
+        };
+
+        chunk.visible = visible;
+        self.dirty = true;
+        true
+    }
+
+    /// Resizes the chunk whose origin is exactly `(x, y)`, see `Chunk::resize`. Returns false
+    /// if there is none. The resize isn't recorded in the history.
+    pub fn resize_chunk(&mut self, x: i32, y: i32, new_x: i32, new_y: i32) -> bool {
+        let Some(chunk) = self
+            .chunks
+            .iter_mut()
+            .find(|chunk| chunk.x == x && chunk.y == y)
+        else {
+            return false;
+        };
+
+        chunk.resize(new_x, new_y);
+        self.reindex();
+        true
+    }
+
+    /// Removes and returns the chunk whose origin is exactly `(x, y)`
+    pub fn remove_chunk(&mut self, x: i32, y: i32) -> Option<Chunk> {
+        let index = self
+            .chunks
+            .iter()
+            .position(|chunk| chunk.x == x && chunk.y == y)?;
+        let chunk = self.chunks.remove(index);
+        self.reindex();
+        Some(chunk)
+    }
+
+    pub fn add_chunk(&mut self, x: i32, y: i32, size_x: i32, size_y: i32) {
+        let chunk = Chunk::from_flat(x, y, size_x, size_y, vec![0; (size_x * size_y) as usize]);
+        self.insert_chunk(chunk);
+    }
+
+    /// Adds a chunk at `(x, y)` holding `data`, rows from the top, sized after it. Fails if
+    /// the rows differ in length.
+    pub fn add_chunk_with_data(
+        &mut self,
+        x: i32,
+        y: i32,
+        data: Vec<Vec<bool>>,
+    ) -> Result<(), TileError> {
+        let size_x = data.first().map_or(0, |row| row.len());
+        if data.iter().any(|row| row.len() != size_x) {
+            return Err(TileError::RaggedChunk);
+        }
+
+        let size_y = data.len() as i32;
+        self.insert_chunk(Chunk::new(x, y, size_x as i32, size_y, data));
+        Ok(())
+    }
+
+    /// Adds an already built chunk to the map
+    pub fn insert_chunk(&mut self, chunk: Chunk) {
+        self.chunks.push(chunk);
+        self.reindex();
+    }
+
+    /// Hands every tile overlapping `view` to `renderer`, see `draw_region`. Drawing goes
+    /// through this with raylib, and a `RecordingRenderer` sees the same tiles without a window.
+    pub fn draw_to<R: TileRenderer>(&self, renderer: &mut R, view: Rectangle) {
+        self.draw_scaled(renderer, view, self.scale, Vector2::new(0.0, 0.0));
+    }
+
+    /// Draws the filled tiles as plain `solid` rectangles on an `empty` background, ignoring
+    /// the sprites, for a HUD minimap. The active bounds are scaled to fit `dest` keeping
+    /// their aspect ratio and centered in it. That's one `draw_rect` for the background plus
+    /// one per filled tile, cheap enough to call every frame.
+    pub fn draw_minimap_to<R: TileRenderer>(
+        &self,
+        renderer: &mut R,
+        dest: Rectangle,
+        solid: Color,
+        empty: Color,
+    ) {
+        let Some((min_x, min_y, max_x, max_y)) = self.active_bounds() else {
+            return;
+        };
+
+        let (width, height) = ((max_x - min_x) as f32, (max_y - min_y) as f32);
+        let cell = f32::min(dest.width / width, dest.height / height);
+        let origin = Vector2::new(
+            dest.x + (dest.width - width * cell) / 2.0,
+            dest.y + (dest.height - height * cell) / 2.0,
+        );
+
+        renderer.draw_rect(
+            Rectangle::new(origin.x, origin.y, width * cell, height * cell),
+            empty,
+        );
+        for (x, y) in self.iter_active() {
+            renderer.draw_rect(
+                Rectangle::new(
+                    origin.x + (x - min_x) as f32 * cell,
+                    origin.y + (y - min_y) as f32 * cell,
+                    cell,
+                    cell,
+                ),
+                solid,
+            );
+        }
+    }
+
+    /// Draws the tiles overlapping `view` at `scale`, moved by `offset` on screen. A wrapping
+    /// map is drawn again for every repeat of it the view overlaps.
+    fn draw_scaled<R: TileRenderer>(
+        &self,
+        renderer: &mut R,
+        view: Rectangle,
+        scale: f32,
+        offset: Vector2,
+    ) {
+        let Some((wrap_x, wrap_y)) = self.wrap else {
+            self.draw_cells(renderer, view, scale, offset);
+            return;
+        };
+
+        let (size_x, size_y) = self.rules.tile_size();
+        let period = Vector2::new(
+            (wrap_x * size_x) as f32 * scale,
+            (wrap_y * size_y) as f32 * scale,
+        );
+        if period.x <= 0.0 || period.y <= 0.0 {
+            return;
+        }
+
+        // One extra repeat on each side covers the half tile the dual grid is drawn off by
+        let first_x = f32::floor(view.x / period.x) as i32 - 1;
+        let first_y = f32::floor(view.y / period.y) as i32 - 1;
+        let last_x = f32::floor((view.x + view.width) / period.x) as i32 + 1;
+        let last_y = f32::floor((view.y + view.height) / period.y) as i32 + 1;
+        for repeat_y in first_y..=last_y {
+            for repeat_x in first_x..=last_x {
+                let (shift_x, shift_y) = (repeat_x as f32 * period.x, repeat_y as f32 * period.y);
+                self.draw_cells(
+                    renderer,
+                    Rectangle::new(view.x - shift_x, view.y - shift_y, view.width, view.height),
+                    scale,
+                    Vector2::new(offset.x + shift_x, offset.y + shift_y),
+                );
+            }
+        }
+    }
+
+    /// Draws the tiles of the chunks overlapping `view` at `scale`, see `draw_scaled`
+    fn draw_cells<R: TileRenderer>(
+        &self,
+        renderer: &mut R,
+        view: Rectangle,
+        scale: f32,
+        offset: Vector2,
+    ) {
+        let (size_x, size_y) = self.rules.tile_size();
+        let tile_size = Vector2::new(size_x as f32 * scale, size_y as f32 * scale);
+        if tile_size.x <= 0.0 || tile_size.y <= 0.0 {
+            return;
+        }
+
+        // Dual grid tiles are drawn half a tile off the grid, so shift the view back before
+        // snapping. Blob tiles sit on the grid.
+        let dual_grid = self.rules.mode() == RuleMode::DualGrid;
+        let shift = if dual_grid { 0.5 } else { 0.0 };
+        let min_x = f32::floor((view.x - tile_size.x * shift) / tile_size.x) as i32;
+        let min_y = f32::floor((view.y - tile_size.y * shift) / tile_size.y) as i32;
+        let max_x = f32::floor((view.x + view.width - tile_size.x * shift) / tile_size.x) as i32;
+        let max_y = f32::floor((view.y + view.height - tile_size.y * shift) / tile_size.y) as i32;
+        let first = if dual_grid { -1 } else { 0 };
+
+        // A wrapping map draws each cell once per repeat, the cells left and above of it
+        // being the last ones of the repeat before
+        let (min_x, min_y, max_x, max_y) = match self.wrap {
+            Some((w, h)) => (
+                min_x.max(0),
+                min_y.max(0),
+                max_x.min(w - 1),
+                max_y.min(h - 1),
+            ),
+            None => (min_x, min_y, max_x, max_y),
+        };
+
+        let mut queue: Option<Vec<(&TileRule, &TileSprite, Rectangle, Color)>> =
+            if self.draw_batching {
+                Some(vec![])
+            } else {
+                None
+            };
+
+        for chunk in self.chunks.iter().filter(|chunk| chunk.visible) {
+            // -1 Cause we want to draw the left and top edge tiles not present in any chunks
+            let start_x = i32::max(first, min_x - chunk.x);
+            let start_y = i32::max(first, min_y - chunk.y);
+            let end_x = i32::min(chunk.size_x - 1, max_x - chunk.x);
+            let end_y = i32::min(chunk.size_y - 1, max_y - chunk.y);
+            if start_x > end_x || start_y > end_y {
+                continue;
+            }
+
+            let cache = if self.rule_cache {
+                Some(self.cached_rules(chunk))
+            } else {
+                None
+            };
+
+            for y in start_y..=end_y {
+                for x in start_x..=end_x {
+                    let cached = cache.as_ref().and_then(|cache| {
+                        cache.get(((y + 1) * (chunk.size_x + 1) + x + 1) as usize)
+                    });
+                    let cell = match cached {
+                        Some(&cell) => cell,
+                        None => self.cell_rule(chunk, x, y),
+                    };
+                    let Some(index) = cell else {
+                        continue;
+                    };
+
+                    // Combinations missing from the rules without a default are left blank
+                    let sprite_rule = match self.rules.rule_or_default(index) {
+                        Some(rule) => rule,
+                        None => continue,
+                    };
+                    let sprite =
+                        sprite_rule.sprite_at(self.time, chunk.x + x, chunk.y + y, self.seed);
+                    let size_x = sprite_rule.size_x as f32 * scale;
+                    let size_y = sprite_rule.size_y as f32 * scale;
+                    let tint = self
+                        .get_tint(chunk.x + x, chunk.y + y)
+                        .unwrap_or(Color::WHITE);
+                    let dest = Rectangle::new(
+                        (chunk.x + x) as f32 * size_x + size_x * shift + offset.x,
+                        (chunk.y + y) as f32 * size_y + size_y * shift + offset.y,
+                        size_x,
+                        size_y,
+                    );
+
+                    match queue {
+                        Some(ref mut queue) => queue.push((sprite_rule, sprite, dest, tint)),
+                        None => renderer.draw_tile(sprite_rule, sprite, dest, tint),
+                    }
+                }
+            }
+        }
+
+        if let Some(mut queue) = queue {
+            // Drawn cells never overlap, so their order doesn't matter. The sort is stable,
+            // keeping the cells of a rule in the same order every frame.
+            queue.sort_by_key(|(_, sprite, _, _)| {
+                (
+                    sprite.texture,
+                    sprite.source.x as i32,
+                    sprite.source.y as i32,
+                )
+            });
+            for (rule, sprite, dest, tint) in queue {
+                renderer.draw_tile(rule, sprite, dest, tint);
+            }
+        }
+    }
+
+    /// The combination `draw` matches for the cell at world `(x, y)`: the 0-15 index of its
+    /// corners (see `neighbors_to_index`) in dual grid mode, where the cell sits between
+    /// tiles `(x, y)` and `(x + 1, y + 1)`, or the blob mask of the tile in blob mode. `None`
+    /// if no cell is drawn there or the rules don't cover it.
+    pub fn rule_at(&self, x: i32, y: i32) -> Option<usize> {
+        let dual_grid = self.rules.mode() == RuleMode::DualGrid;
+        let first = if dual_grid { -1 } else { 0 };
+        let chunk = self.chunks.iter().find(|chunk| {
+            let (local_x, local_y) = (x - chunk.x, y - chunk.y);
+            (first..chunk.size_x).contains(&local_x) && (first..chunk.size_y).contains(&local_y)
+        })?;
+
+        let (local_x, local_y) = (x - chunk.x, y - chunk.y);
+        self.cell_rule(chunk, local_x, local_y)??;
+        if dual_grid {
+            let corners = self.cell_corners(chunk, local_x, local_y);
+            Some(neighbors_to_index(corners.map(|tile| tile != 0)) as usize)
+        } else {
+            self.cell_blob_mask(chunk, local_x, local_y)
+                .map(|mask| mask as usize)
+        }
+    }
+
+    /// Corner tiles of the cell drawn at chunk-local `(x, y)`, which sits between tiles
+    /// `(x, y)` and `(x + 1, y + 1)`. Every corner is read in world space, so cells on a
+    /// chunk's edge see the tiles of the chunks next to it.
+    fn cell_corners(&self, chunk: &Chunk, x: i32, y: i32) -> [u8; 4] {
+        CORNERS.map(|(dx, dy)| self.get_tile(x + dx + chunk.x, y + dy + chunk.y))
+    }
+
+    /// Rule of the cell drawn at chunk-local `(x, y)`: `None` if nothing is drawn there, and
+    /// `Some(None)` if the rules don't cover it so the default rule is drawn instead
+    fn cell_rule(&self, chunk: &Chunk, x: i32, y: i32) -> Option<Option<usize>> {
+        match self.rules.mode() {
+            RuleMode::DualGrid => Some(self.rules.rule_index(self.cell_corners(chunk, x, y))),
+            RuleMode::Blob => {
+                let mask = self.cell_blob_mask(chunk, x, y)?;
+                Some(self.rules.blob_rule_index(mask))
+            }
+        }
+    }
+
+    /// Blob mask of the tile at chunk-local `(x, y)`, `None` if it isn't drawn
+    fn cell_blob_mask(&self, chunk: &Chunk, x: i32, y: i32) -> Option<u8> {
+        // Only the chunk's own filled tiles are drawn
+        let (x, y) = (chunk.x + x, chunk.y + y);
+        if !chunk.contains(x, y) || !self.get(x, y) {
+            return None;
+        }
+
+        let neighbors = [
+            (0, -1),
+            (1, -1),
+            (1, 0),
+            (1, 1),
+            (0, 1),
+            (-1, 1),
+            (-1, 0),
+            (-1, -1),
+        ]
+        .map(|(dx, dy)| self.get(x + dx, y + dy));
+        Some(blob_mask(neighbors))
+    }
+
+    /// Rule indices of every cell drawn for the chunk, from `(-1, -1)` to
+    /// `(size_x - 1, size_y - 1)` row by row, building them if an edit dropped them
+    fn cached_rules<'a>(&self, chunk: &'a Chunk) -> Ref<'a, Vec<Option<Option<usize>>>> {
+        if chunk.rule_cache.borrow().is_none() {
+            let mut rules = Vec::with_capacity(((chunk.size_x + 1) * (chunk.size_y + 1)) as usize);
+            for y in -1..chunk.size_y {
+                for x in -1..chunk.size_x {
+                    rules.push(self.cell_rule(chunk, x, y));
+                }
+            }
+            chunk.rule_cache.replace(Some(rules));
+        }
+
+        Ref::map(chunk.rule_cache.borrow(), |cache| {
+            cache.as_ref().expect("the cache was just built")
+        })
+    }
+}
+
+/// `map[(x, y)]` reads a tile like `get`, but panics when no chunk holds it, the way indexing
+/// a `Vec` out of bounds does. `get` and `set` stay the non-panicking way in, treating missing
+/// tiles as empty (or following the edge mode). A wrapping map wraps the coordinate first like
+/// `get`, so only tiles its chunks don't cover panic. There is no `IndexMut`, as writes have
+/// to go through `set` to be recorded for undo and redraw.
+impl Index<(i32, i32)> for TileMap {
+    type Output = bool;
+
+    fn index(&self, (x, y): (i32, i32)) -> &bool {
+        let (x, y) = self.wrap_tile(x, y);
+        let chunk = self
+            .get_chunk_at(x, y)
+            .unwrap_or_else(|| panic!("No chunk holds the tile ({}, {})", x, y));
+
+        if chunk.get(x - chunk.x, y - chunk.y) {
+            &true
+        } else {
+            &false
+        }
     }
 }
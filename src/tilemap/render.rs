@@ -0,0 +1,482 @@
+//! Everything touching raylib's textures and drawing, only built with the `render` feature
+
+use std::collections::HashMap;
+use std::ffi::CString;
+
+use raylib::prelude::*;
+
+use super::{neighbors_to_index, RuleMode, TileMap, TileRules, TileSprite};
+use crate::error::TileError;
+use crate::renderer::RaylibRenderer;
+
+impl TileRules {
+    pub fn load(mut self, rl: &mut RaylibHandle, thread: &RaylibThread) -> Result<Self, TileError> {
+        self.build(rl, thread)?;
+        Ok(self)
+    }
+
+    /// Loads rules from yaml text and the atlas at `atlas` in one go, the same as
+    /// `with_bytes_yaml_file`, `with_sprite_atlas` and `load`. Use those for anything more.
+    ///
+    /// ```no_run
+    /// use dualgrid_raylib::tilemap::{TileMap, TileRules};
+    ///
+    /// let (mut rl, thread) = raylib::init().size(640, 480).build();
+    /// let yaml = std::fs::read_to_string("tile_rules.yaml").unwrap();
+    /// let rules = TileRules::from_yaml_str(&yaml, "grass.png", &mut rl, &thread).unwrap();
+    /// let tilemap = TileMap::new(rules).unwrap();
+    /// ```
+    pub fn from_yaml_str(
+        yaml: &str,
+        atlas: &str,
+        rl: &mut RaylibHandle,
+        thread: &RaylibThread,
+    ) -> Result<Self, TileError> {
+        TileRules::new()
+            .with_bytes_yaml_file(yaml.as_bytes())?
+            .with_sprite_atlas(atlas)
+            .load(rl, thread)
+    }
+
+    /// Loads the rules added with `add_rule`, cropping their sprites out of the atlas image
+    /// at `atlas`. The same as `with_sprite_atlas` and `load`.
+    pub fn finalize(
+        self,
+        rl: &mut RaylibHandle,
+        thread: &RaylibThread,
+        atlas: &str,
+    ) -> Result<Self, TileError> {
+        self.with_sprite_atlas(atlas).load(rl, thread)
+    }
+
+    /// Reads the rules file (if it came from a path) and the atlas again, replacing the rules
+    /// in place. The old textures are unloaded, and kept if anything fails.
+    pub fn reload(
+        &mut self,
+        rl: &mut RaylibHandle,
+        thread: &RaylibThread,
+    ) -> Result<(), TileError> {
+        if let (Some(path), Some(rules_file)) = (&self.rules_path, &self.rules_file) {
+            self.rules_file = Some(rules_file.reread(path)?);
+        }
+
+        self.build(rl, thread)
+    }
+
+    /// Frees every texture of the rules right away instead of when they're dropped, leaving
+    /// them empty until the next `reload`. The default rule is cleared as its sprites point
+    /// into the freed textures.
+    pub fn unload(&mut self, _rl: &mut RaylibHandle, _thread: &RaylibThread) {
+        // Dropping a Texture2D unloads it, the handle only proves we're on the GL thread
+        self.textures.clear();
+        self.texture_sources.clear();
+        self.atlas_rects.clear();
+        self.rules.clear();
+        self.default_rule = None;
+        self.build_index();
+    }
+
+    fn build(&mut self, rl: &mut RaylibHandle, thread: &RaylibThread) -> Result<(), TileError> {
+        // Decode every atlas a single time, when the first sprite needs it, and copy every
+        // sprite out of it. Image and Texture2D unload themselves on drop, so the atlases and
+        // every sprite image are freed once uploaded and only the textures stay alive.
+        let sprite_atlas = self.sprite_atlas.clone();
+        let shared_atlas = self.shared_atlas;
+        let mut atlases: HashMap<Option<String>, (Image, Option<usize>)> = HashMap::new();
+        let mut textures: Vec<Texture2D> = vec![];
+        let mut texture_sources = vec![];
+        let mut atlas_rects = vec![];
+        self.build_with(|rule_index, atlas_path, sprite_rect| {
+            let key = atlas_path.map(str::to_string);
+            if !atlases.contains_key(&key) {
+                let path = atlas_path
+                    .or(sprite_atlas.as_deref())
+                    .ok_or(TileError::MissingAtlas)?;
+                let image = Image::load_image(path).map_err(TileError::AtlasLoad)?;
+
+                // A shared atlas is uploaded whole, once
+                let texture = if shared_atlas {
+                    let texture = rl
+                        .load_texture_from_image(thread, &image)
+                        .map_err(TileError::TextureLoad)?;
+                    textures.push(texture);
+                    texture_sources.push((key.clone(), None));
+                    Some(textures.len() - 1)
+                } else {
+                    None
+                };
+                atlases.insert(key.clone(), (image, texture));
+            }
+
+            let (atlas, shared_texture) = &atlases[&key];
+            check_bounds(atlas, rule_index, sprite_rect)?;
+            atlas_rects.push((rule_index, key.clone(), sprite_rect));
+
+            if let Some(texture) = *shared_texture {
+                return Ok(TileSprite {
+                    texture,
+                    source: sprite_rect,
+                });
+            }
+
+            textures.push(crop_texture(rl, thread, atlas, sprite_rect)?);
+            texture_sources.push((key, Some(sprite_rect)));
+            Ok(TileSprite {
+                texture: textures.len() - 1,
+                source: Rectangle::new(0.0, 0.0, sprite_rect.width, sprite_rect.height),
+            })
+        })?;
+
+        for texture in textures.iter_mut() {
+            self.prepare_texture(thread, texture);
+        }
+        self.textures = textures;
+        self.texture_sources = texture_sources;
+        self.atlas_rects = atlas_rects;
+        self.headless = false;
+        Ok(())
+    }
+
+    /// Swaps the main atlas for `new_atlas`, a tilesheet laid out like the current one, like
+    /// a night version of the tileset. Every sprite from it is cropped again from the same
+    /// place without parsing the rules, so the rules and their neighbors stay the same, while
+    /// sprites from the named atlases of the rules file keep theirs. The old textures are
+    /// freed, or kept if anything fails, and later reloads read the new atlas.
+    pub fn reskin(
+        &mut self,
+        rl: &mut RaylibHandle,
+        thread: &RaylibThread,
+        new_atlas: &str,
+    ) -> Result<(), TileError> {
+        let atlas = Image::load_image(new_atlas).map_err(TileError::AtlasLoad)?;
+        for (rule_index, key, rect) in self.atlas_rects.iter() {
+            if key.is_none() {
+                check_bounds(&atlas, *rule_index, *rect)?;
+            }
+        }
+
+        let mut swapped = vec![];
+        for (i, (key, rect)) in self.texture_sources.iter().enumerate() {
+            if key.is_some() {
+                continue;
+            }
+
+            let texture = match rect {
+                Some(rect) => crop_texture(rl, thread, &atlas, *rect)?,
+                None => rl
+                    .load_texture_from_image(thread, &atlas)
+                    .map_err(TileError::TextureLoad)?,
+            };
+            swapped.push((i, texture));
+        }
+
+        for (i, mut texture) in swapped {
+            self.prepare_texture(thread, &mut texture);
+            self.textures[i] = texture;
+        }
+        self.sprite_atlas = Some(new_atlas.to_string());
+        Ok(())
+    }
+
+    /// Sets how the sprites are sampled when drawn scaled, for every texture loaded now and
+    /// later. Point filtering, the default, keeps pixel art crisp when `draw` scales it up,
+    /// smooth art can opt into `TEXTURE_FILTER_BILINEAR` instead.
+    pub fn set_texture_filter(&mut self, thread: &RaylibThread, filter: TextureFilter) {
+        self.texture_filter = filter;
+        for texture in self.textures.iter_mut() {
+            texture.set_texture_filter(thread, filter);
+        }
+    }
+
+    /// Generates mipmaps for every texture, now and on every later load, and switches to
+    /// trilinear filtering so tiles drawn zoomed far out stop shimmering. Pixel art drawn at
+    /// 1:1 or scaled up doesn't need it. A later `set_texture_filter` replaces the filter but
+    /// keeps the mipmaps, which only the trilinear and anisotropic filters sample.
+    pub fn generate_mipmaps(&mut self, thread: &RaylibThread) {
+        self.mipmaps = true;
+        self.texture_filter = TextureFilter::TEXTURE_FILTER_TRILINEAR;
+        let mut textures = std::mem::take(&mut self.textures);
+        for texture in textures.iter_mut() {
+            self.prepare_texture(thread, texture);
+        }
+        self.textures = textures;
+    }
+
+    /// Applies the mipmaps and filter settings to a freshly loaded texture
+    fn prepare_texture(&self, thread: &RaylibThread, texture: &mut Texture2D) {
+        if self.mipmaps {
+            texture.gen_texture_mipmaps();
+        }
+        texture.set_texture_filter(thread, self.texture_filter);
+    }
+
+    pub fn texture(&self, sprite: &TileSprite) -> &Texture2D {
+        &self.textures[sprite.texture]
+    }
+}
+
+/// Fails if `rect`, a sprite of rule `rule_index`, reaches outside of `atlas`. Cropping past
+/// the atlas would clamp the sprite or read garbage.
+fn check_bounds(atlas: &Image, rule_index: usize, rect: Rectangle) -> Result<(), TileError> {
+    if rect.x < 0.0
+        || rect.y < 0.0
+        || rect.x + rect.width > atlas.width() as f32
+        || rect.y + rect.height > atlas.height() as f32
+    {
+        return Err(TileError::SpriteOutOfBounds { rule_index, rect });
+    }
+    Ok(())
+}
+
+/// Copies only the sprite at `rect` out of the atlas and converts it to a texture
+fn crop_texture(
+    rl: &mut RaylibHandle,
+    thread: &RaylibThread,
+    atlas: &Image,
+    rect: Rectangle,
+) -> Result<Texture2D, TileError> {
+    let image = atlas.from_image(rect);
+    rl.load_texture_from_image(thread, &image)
+        .map_err(TileError::TextureLoad)
+}
+
+impl TileMap {
+    /// Reloads the rules from disk, see `TileRules::reload`
+    pub fn reload_rules(
+        &mut self,
+        rl: &mut RaylibHandle,
+        thread: &RaylibThread,
+    ) -> Result<(), TileError> {
+        self.rules.reload(rl, thread)?;
+        self.invalidate_all();
+        Ok(())
+    }
+
+    pub fn draw(&self, d: &mut RaylibDrawHandle) {
+        let view = Rectangle::new(
+            0.0,
+            0.0,
+            d.get_screen_width() as f32,
+            d.get_screen_height() as f32,
+        );
+        self.draw_region(d, view);
+    }
+
+    /// Draws the map moved by `origin` on screen, so world `(0, 0)` lands on `origin`. A
+    /// simpler way than a camera to place a map at a fixed spot, like a HUD panel.
+    pub fn draw_at(&self, d: &mut RaylibDrawHandle, origin: Vector2) {
+        let view = Rectangle::new(
+            -origin.x,
+            -origin.y,
+            d.get_screen_width() as f32,
+            d.get_screen_height() as f32,
+        );
+        self.draw_scaled(
+            &mut RaylibRenderer::new(d, &self.rules),
+            view,
+            self.scale,
+            origin,
+        );
+    }
+
+    /// Draws the tiles visible through `camera`, call it inside the camera's 2D mode
+    pub fn draw_with_camera(&self, d: &mut RaylibMode2D<RaylibDrawHandle>, camera: &Camera2D) {
+        let width = d.get_screen_width() as f32;
+        let height = d.get_screen_height() as f32;
+
+        // Project every screen corner so rotated cameras still get the whole visible area
+        let corners = [
+            d.get_screen_to_world2D(Vector2::new(0.0, 0.0), *camera),
+            d.get_screen_to_world2D(Vector2::new(width, 0.0), *camera),
+            d.get_screen_to_world2D(Vector2::new(0.0, height), *camera),
+            d.get_screen_to_world2D(Vector2::new(width, height), *camera),
+        ];
+        let min_x = corners.iter().map(|c| c.x).fold(f32::INFINITY, f32::min);
+        let min_y = corners.iter().map(|c| c.y).fold(f32::INFINITY, f32::min);
+        let max_x = corners
+            .iter()
+            .map(|c| c.x)
+            .fold(f32::NEG_INFINITY, f32::max);
+        let max_y = corners
+            .iter()
+            .map(|c| c.y)
+            .fold(f32::NEG_INFINITY, f32::max);
+
+        self.draw_region(
+            d,
+            Rectangle::new(min_x, min_y, max_x - min_x, max_y - min_y),
+        );
+    }
+
+    /// Draws only the tiles overlapping `view`, given in world space (screen space without a camera)
+    pub fn draw_region<D: RaylibDraw>(&self, d: &mut D, view: Rectangle) {
+        self.draw_to(&mut RaylibRenderer::new(d, &self.rules), view);
+    }
+
+    /// Draws the borders of every tile inside the chunks' bounding box (see `extent`), lined up
+    /// with `tile_rect`, to check where tiles are against the dual grid sprites drawn half a
+    /// tile off. With `labels` every tile also shows its coordinate. Call it after `draw`,
+    /// it's never drawn otherwise.
+    pub fn draw_debug_grid<D: RaylibDraw>(&self, d: &mut D, color: Color, labels: bool) {
+        let Some((min_x, min_y, max_x, max_y)) = self.extent() else {
+            return;
+        };
+
+        let top_left = self.tile_rect(min_x, min_y);
+        let bottom_right = self.tile_rect(max_x, max_y);
+        for x in min_x..=max_x {
+            let line_x = self.tile_rect(x, min_y).x;
+            d.draw_line_v(
+                Vector2::new(line_x, top_left.y),
+                Vector2::new(line_x, bottom_right.y),
+                color,
+            );
+        }
+        for y in min_y..=max_y {
+            let line_y = self.tile_rect(min_x, y).y;
+            d.draw_line_v(
+                Vector2::new(top_left.x, line_y),
+                Vector2::new(bottom_right.x, line_y),
+                color,
+            );
+        }
+
+        if !labels {
+            return;
+        }
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let rect = self.tile_rect(x, y);
+                d.draw_text(
+                    &format!("{},{}", x, y),
+                    rect.x as i32 + 2,
+                    rect.y as i32 + 2,
+                    10,
+                    color,
+                );
+            }
+        }
+    }
+
+    /// Draws the map as a minimap fitted into `dest`, see `draw_minimap_to`
+    pub fn draw_minimap<D: RaylibDraw>(
+        &self,
+        d: &mut D,
+        dest: Rectangle,
+        solid: Color,
+        empty: Color,
+    ) {
+        self.draw_minimap_to(&mut RaylibRenderer::new(d, &self.rules), dest, solid, empty);
+    }
+
+    /// Writes on every drawn cell the combination it matched: the 0-15 index of its corners
+    /// (see `neighbors_to_index`) in dual grid mode, or its blob mask in blob mode. The cells
+    /// are sampled the way `draw` samples them, so a wrong sprite under a right number points
+    /// at the rules, and a wrong number at the map. Corners of any material count as filled.
+    pub fn draw_debug_rule_ids<D: RaylibDraw>(&self, d: &mut D, color: Color) {
+        let dual_grid = self.rules.mode() == RuleMode::DualGrid;
+        let first = if dual_grid { -1 } else { 0 };
+
+        for chunk in self.chunks.iter() {
+            for y in first..chunk.size_y {
+                for x in first..chunk.size_x {
+                    let id = if dual_grid {
+                        neighbors_to_index(self.cell_corners(chunk, x, y).map(|tile| tile != 0))
+                    } else {
+                        match self.cell_blob_mask(chunk, x, y) {
+                            Some(mask) => mask,
+                            None => continue,
+                        }
+                    };
+
+                    let rect = self.cell_rect(chunk.x + x, chunk.y + y);
+                    let text = id.to_string();
+                    d.draw_text(
+                        &text,
+                        (rect.x + rect.width / 2.0) as i32 - 3 * text.len() as i32,
+                        (rect.y + rect.height / 2.0) as i32 - 5,
+                        10,
+                        color,
+                    );
+                }
+            }
+        }
+    }
+
+    /// Draws the filled part of the map (see `active_bounds`) into an image at one pixel per
+    /// atlas pixel, ignoring the scale. The image starts at the top left corner of the first
+    /// tile, so the half tile offset of the dual grid is cut off and tile `(x, y)` of the
+    /// bounds covers the pixels from `(x * width, y * height)`.
+    pub fn render_to_image(
+        &self,
+        mut rl: &mut RaylibHandle,
+        thread: &RaylibThread,
+    ) -> Result<Image, TileError> {
+        let (min_x, min_y, max_x, max_y) = self.active_bounds().ok_or(TileError::EmptyMap)?;
+        let (size_x, size_y) = self.rules.tile_size();
+        let view = Rectangle::new(
+            (min_x * size_x) as f32,
+            (min_y * size_y) as f32,
+            ((max_x - min_x) * size_x) as f32,
+            ((max_y - min_y) * size_y) as f32,
+        );
+
+        let mut target = rl
+            .load_render_texture(thread, view.width as u32, view.height as u32)
+            .map_err(TileError::TextureLoad)?;
+        {
+            // Texture mode is started from `&mut RaylibHandle` itself, hence the mut binding
+            let mut d = rl.begin_texture_mode(thread, &mut target);
+            d.clear_background(Color::BLANK);
+            self.draw_scaled(
+                &mut RaylibRenderer::new(&mut d, &self.rules),
+                view,
+                1.0,
+                Vector2::new(-view.x, -view.y),
+            );
+        }
+
+        let mut image = target
+            .texture()
+            .load_image()
+            .map_err(TileError::TextureLoad)?;
+        // Render textures are stored upside down
+        image.flip_vertical();
+        Ok(image)
+    }
+
+    /// Renders the map with `render_to_image` and saves it as a png at `path`
+    pub fn export_png(
+        &self,
+        rl: &mut RaylibHandle,
+        thread: &RaylibThread,
+        path: &str,
+    ) -> Result<(), TileError> {
+        let image = self.render_to_image(rl, thread)?;
+        let c_path = CString::new(path).map_err(|_| TileError::ExportImage(path.to_string()))?;
+
+        // Image::export_image drops the result, ask raylib directly whether it was written
+        if !unsafe { raylib::ffi::ExportImage(*image.as_ref(), c_path.as_ptr()) } {
+            return Err(TileError::ExportImage(path.to_string()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sprites_have_to_fit_in_the_atlas() {
+        let atlas = Image::gen_image_color(32, 16, Color::WHITE);
+        assert!(check_bounds(&atlas, 0, Rectangle::new(24.0, 8.0, 8.0, 8.0)).is_ok());
+
+        let past_edge = Rectangle::new(32.0, 8.0, 8.0, 8.0);
+        assert!(matches!(
+            check_bounds(&atlas, 3, past_edge),
+            Err(TileError::SpriteOutOfBounds { rule_index: 3, rect }) if rect == past_edge
+        ));
+        assert!(check_bounds(&atlas, 0, Rectangle::new(0.0, -8.0, 8.0, 8.0)).is_err());
+    }
+}
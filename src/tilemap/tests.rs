@@ -0,0 +1,1086 @@
+use super::*;
+use crate::renderer::{DrawCall, RecordingRenderer};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// The rules of the demo, loaded without a window
+pub(crate) fn rules() -> TileRules {
+    TileRules::new()
+        .with_bytes_yaml_file(include_bytes!("../../include/tile_rules.yaml"))
+        .unwrap()
+        .load_headless()
+        .unwrap()
+}
+
+/// A map holding a single chunk at the origin, see `TileMap::from_ascii`
+pub(crate) fn map(text: &str) -> TileMap {
+    TileMap::from_ascii(text, rules()).unwrap()
+}
+
+/// Corners of every rule drawn with its top left at `(x, y)` on screen
+fn drawn_at(map: &TileMap, x: f32, y: f32) -> Vec<[u8; 4]> {
+    let mut renderer = RecordingRenderer::new();
+    map.draw_to(&mut renderer, Rectangle::new(0.0, 0.0, 640.0, 480.0));
+    renderer
+        .calls
+        .iter()
+        .filter(|call| call.dest.x == x && call.dest.y == y)
+        .map(|call| call.neighbors)
+        .collect()
+}
+
+/// Everything drawn for a filled 2x2 block with `rules`, to compare rules from different files
+fn draw_block(rules: TileRules) -> Vec<DrawCall> {
+    let mut map = TileMap::new(rules.load_headless().unwrap()).unwrap();
+    map.add_chunk(0, 0, 4, 4);
+    map.fill_rect(1, 1, 2, 2, true);
+    let mut renderer = RecordingRenderer::new();
+    map.draw_to(&mut renderer, Rectangle::new(0.0, 0.0, 640.0, 480.0));
+    renderer.calls
+}
+
+#[test]
+fn cells_on_a_chunk_seam_see_both_chunks() {
+    let mut map = TileMap::new(rules()).unwrap();
+    map.add_chunk(0, 0, 2, 2);
+    map.add_chunk(2, 0, 2, 2);
+    map.set(1, 1, true);
+    map.set(2, 1, true);
+
+    // The cell between tiles (1, 0) and (2, 1) is drawn 16 pixels past the 32 pixel tile
+    let seam = drawn_at(&map, 48.0, 16.0);
+    assert!(!seam.is_empty());
+    assert!(seam.iter().all(|&corners| corners == [0, 0, 1, 1]));
+
+    let seam = drawn_at(&map, 48.0, 48.0);
+    assert!(!seam.is_empty());
+    assert!(seam.iter().all(|&corners| corners == [1, 1, 0, 0]));
+    assert_eq!(
+        map.rule_at(1, 0),
+        Some(neighbors_to_index([false, false, true, true]) as usize)
+    );
+}
+
+#[cfg(feature = "ron")]
+#[test]
+fn ron_rules_match_the_yaml_ones() {
+    let ron = "(size: 8, rules: [
+        (neighbors: [false, false, true, true], sprite: (x: 24, y: 0)),
+        (neighbors: [true, true, false, false], sprite: (x: 8, y: 16)),
+        (neighbors: [true, true, true, true], sprite: [(x: 16, y: 8), (x: 0, y: 8, weight: 2.0)]),
+    ])";
+    let yaml = "size: 8
+rules:
+  - neighbors: [false, false, true, true]
+    sprite: { x: 24, y: 0 }
+  - neighbors: [true, true, false, false]
+    sprite: { x: 8, y: 16 }
+  - neighbors: [true, true, true, true]
+    sprite: [{ x: 16, y: 8 }, { x: 0, y: 8, weight: 2.0 }]
+";
+
+    let from_ron = draw_block(
+        TileRules::new()
+            .with_bytes_ron_file(ron.as_bytes())
+            .unwrap(),
+    );
+    let from_yaml = draw_block(
+        TileRules::new()
+            .with_bytes_yaml_file(yaml.as_bytes())
+            .unwrap(),
+    );
+    assert!(!from_ron.is_empty());
+    assert_eq!(from_ron, from_yaml);
+}
+
+#[test]
+fn missing_combinations_lists_the_uncovered_corners() {
+    let yaml = std::str::from_utf8(include_bytes!("../../include/tile_rules.yaml"))
+        .unwrap()
+        .replace(
+            "  - neighbors: [true, true, true, true]\n    sprite: { x: 16, y: 8 }\n",
+            "",
+        )
+        .replace(
+            "  - neighbors: [false, false, false, false]\n    sprite: { x: 0, y: 24 }\n",
+            "",
+        );
+    let incomplete = TileRules::new()
+        .with_bytes_yaml_file(yaml.as_bytes())
+        .unwrap()
+        .load_headless()
+        .unwrap();
+
+    assert_eq!(
+        incomplete.missing_combinations(),
+        vec![[false, false, false, false], [true, true, true, true]]
+    );
+    assert!(rules().missing_combinations().is_empty());
+}
+
+#[test]
+fn chunk_tiles_round_trip_at_the_corners() {
+    let rows = vec![
+        vec![true, false, false],
+        vec![false, false, false],
+        vec![false, false, true],
+    ];
+    let mut chunk = Chunk::new(0, 0, 3, 3, rows);
+    assert_eq!(chunk.data, vec![1, 0, 0, 0, 0, 0, 0, 0, 1]);
+
+    for (x, y) in [(0, 0), (2, 0), (0, 2), (2, 2)] {
+        chunk.set(x, y, true);
+        assert!(chunk.get(x, y));
+        chunk.set(x, y, false);
+        assert!(!chunk.get(x, y));
+    }
+    assert!(chunk.data.iter().all(|&tile| tile == 0));
+
+    // Past the edges nothing is read or written
+    chunk.set(3, 0, true);
+    chunk.set(0, -1, true);
+    assert!(!chunk.get(3, 0));
+    assert!(chunk.data.iter().all(|&tile| tile == 0));
+}
+
+#[test]
+fn fill_rect_spans_chunks() {
+    let mut map = TileMap::new(rules()).unwrap();
+    map.add_chunk(0, 0, 4, 4);
+    map.add_chunk(4, 0, 4, 4);
+    map.fill_rect(2, 1, 4, 2, true);
+
+    assert_eq!(map.to_ascii(), "........\n..####..\n..####..\n........\n");
+    assert!(map.get(3, 1) && map.get(4, 1) && map.get(5, 2));
+    assert!(!map.get(1, 1) && !map.get(6, 2) && !map.get(2, 3));
+
+    // Tiles outside of every chunk are skipped
+    map.fill_rect(6, 2, 4, 4, true);
+    assert_eq!(map.to_ascii(), "........\n..####..\n..######\n......##\n");
+}
+
+#[test]
+fn screen_and_tile_coordinates_agree_at_any_scale() {
+    for scale in [1.0, 2.0, 4.0, 2.5] {
+        let map = map("..\n").with_scale(scale);
+        let size = 8.0 * scale;
+
+        assert_eq!(
+            map.screen_to_tile(Vector2::new(3.0 * size, 2.0 * size)),
+            (3, 2)
+        );
+        assert_eq!(
+            map.screen_to_tile(Vector2::new(4.0 * size - 0.5, 3.0 * size - 0.5)),
+            (3, 2)
+        );
+        assert_eq!(
+            map.screen_to_tile(Vector2::new(-0.5, -size - 0.5)),
+            (-1, -2)
+        );
+
+        for (x, y) in [(0, 0), (3, 2), (-4, 7)] {
+            let center = map.tile_to_screen(x, y);
+            assert_eq!(
+                center,
+                Vector2::new((x as f32 + 0.5) * size, (y as f32 + 0.5) * size)
+            );
+            assert_eq!(map.screen_to_tile(center), (x, y));
+
+            // The four cells showing the tile meet at its center
+            let cell = map.cell_rect(x - 1, y - 1);
+            assert_eq!(
+                Vector2::new(cell.x + cell.width, cell.y + cell.height),
+                center
+            );
+        }
+    }
+}
+
+#[test]
+fn json_rules_match_the_yaml_ones() {
+    let json = r#"{
+        "size": 8,
+        "rules": [
+            { "neighbors": [false, false, true, true], "sprite": { "x": 24, "y": 0 } },
+            { "neighbors": [true, true, false, false], "sprite": { "x": 8, "y": 16 } },
+            { "neighbors": [true, true, true, true], "sprite": { "x": 16, "y": 8 } }
+        ]
+    }"#;
+    let yaml = "size: 8
+rules:
+  - neighbors: [false, false, true, true]
+    sprite: { x: 24, y: 0 }
+  - neighbors: [true, true, false, false]
+    sprite: { x: 8, y: 16 }
+  - neighbors: [true, true, true, true]
+    sprite: { x: 16, y: 8 }
+";
+
+    let from_json = draw_block(
+        TileRules::new()
+            .with_bytes_json_file(json.as_bytes())
+            .unwrap(),
+    );
+    let from_yaml = draw_block(
+        TileRules::new()
+            .with_bytes_yaml_file(yaml.as_bytes())
+            .unwrap(),
+    );
+    assert!(!from_json.is_empty());
+    assert_eq!(from_json, from_yaml);
+}
+
+#[test]
+fn fill_with_paints_a_checkerboard() {
+    let mut map = TileMap::new(rules()).unwrap();
+    map.add_chunk(0, 0, 4, 3);
+    map.add_chunk(4, 0, 2, 3);
+    map.fill_with(-1, -1, 8, 5, |x, y| (x + y) % 2 == 0);
+
+    assert_eq!(map.to_ascii(), "#.#.#.\n.#.#.#\n#.#.#.\n");
+    assert!(map.get_chunk_at(-1, -1).is_none());
+}
+
+#[test]
+fn ca_step_reads_the_state_from_before_the_step() {
+    let mut cave = map(".....\n.###.\n.###.\n.###.\n.....\n");
+    cave.ca_step(5, 4);
+    assert_eq!(cave.to_ascii(), ".....\n..#..\n.###.\n..#..\n.....\n");
+
+    // Tiles outside of the chunk count as filled with EdgeMode::Filled
+    let mut walled = map("...\n...\n...\n");
+    walled.set_edge_mode(EdgeMode::Filled);
+    walled.ca_step(5, 4);
+    assert_eq!(walled.to_ascii(), "#.#\n...\n#.#\n");
+}
+
+#[test]
+fn active_bounds_hold_every_filled_tile() {
+    let mut map = TileMap::new(rules()).unwrap();
+    assert_eq!(map.active_bounds(), None);
+    map.add_chunk(0, 0, 8, 8);
+    map.add_chunk(-8, 0, 8, 8);
+    assert_eq!(map.active_bounds(), None);
+
+    map.set(2, 5, true);
+    assert_eq!(map.active_bounds(), Some((2, 5, 3, 6)));
+    map.set(6, 1, true);
+    map.set(-3, 7, true);
+    assert_eq!(map.active_bounds(), Some((-3, 1, 7, 8)));
+}
+
+#[test]
+fn crop_to_active_keeps_tiles_in_place() {
+    let mut map = TileMap::new(rules()).unwrap();
+    map.add_chunk(0, 0, 8, 8);
+    map.add_chunk(8, 0, 8, 8);
+    let filled = [(3, 2), (9, 4), (5, 6)];
+    for (x, y) in filled {
+        map.set(x, y, true);
+    }
+    map.crop_to_active();
+
+    assert_eq!(map.extent(), Some((3, 2, 10, 7)));
+    assert_eq!(map.chunks().count(), 1);
+    assert_eq!(
+        map.iter_active().collect::<Vec<_>>(),
+        vec![(3, 2), (9, 4), (5, 6)]
+    );
+    for (x, y) in filled {
+        assert!(map.get(x, y));
+    }
+
+    map.fill_all(false);
+    map.crop_to_active();
+    assert_eq!(map.extent(), None);
+}
+
+#[test]
+fn pasting_a_copied_region_reproduces_it() {
+    let mut map = map("##.....\n.#.....\n#......\n.......\n");
+    let copied = map.get_region(0, 0, 2, 3);
+    assert_eq!(
+        copied,
+        vec![vec![true, true], vec![false, true], vec![true, false]]
+    );
+
+    map.set_region(4, 1, &copied);
+    assert_eq!(map.get_region(4, 1, 2, 3), copied);
+    assert_eq!(map.to_ascii(), "##.....\n.#..##.\n#....#.\n....#..\n");
+
+    // Past the chunk the write is clipped and the read follows the edge mode
+    map.set_region(6, 2, &copied);
+    assert_eq!(
+        map.get_region(6, 2, 2, 2),
+        vec![vec![true, false], vec![false, false]]
+    );
+}
+
+#[test]
+fn undo_restores_the_map_stroke_by_stroke() {
+    let original = "....\n....\n....\n";
+    let mut map = map(original);
+    map.enable_history(10);
+
+    map.begin_stroke();
+    map.set(0, 0, true);
+    map.set(1, 0, true);
+    map.end_stroke();
+    map.set(3, 2, true);
+    let edited = map.to_ascii();
+
+    assert!(map.undo());
+    assert_eq!(map.to_ascii(), "##..\n....\n....\n");
+    assert!(map.undo());
+    assert_eq!(map.to_ascii(), original);
+    assert!(!map.undo());
+
+    assert!(map.redo());
+    assert!(map.redo());
+    assert_eq!(map.to_ascii(), edited);
+    assert!(!map.redo());
+}
+
+#[test]
+fn bulk_edits_undo_as_one_stroke() {
+    let original = "#...\n.#..\n....\n";
+    let mut map = map(original);
+    map.enable_history(10);
+
+    let edits: [fn(&mut TileMap); 6] = [
+        |map| map.fill_rect(1, 1, 3, 2, true),
+        |map| map.invert(),
+        |map| map.set_line(0, 0, 3, 0, false),
+        |map| map.flood_fill(1, 0, true),
+        |map| map.ca_step(9, 4),
+        |map| {
+            map.clear_chunk(0, 0);
+        },
+    ];
+    let mut states = vec![map.to_ascii()];
+    for edit in edits {
+        edit(&mut map);
+        assert_ne!(&map.to_ascii(), states.last().unwrap());
+        states.push(map.to_ascii());
+    }
+
+    // Every undo reverts a whole edit
+    states.pop();
+    while let Some(state) = states.pop() {
+        assert!(map.undo());
+        assert_eq!(map.to_ascii(), state);
+    }
+    assert!(!map.undo());
+
+    // Inside an open stroke they join it
+    map.begin_stroke();
+    map.fill_all(true);
+    map.set(0, 0, false);
+    map.end_stroke();
+    assert!(map.undo());
+    assert_eq!(map.to_ascii(), original);
+}
+
+#[test]
+fn history_keeps_only_the_last_strokes() {
+    let mut map = map("....\n");
+    map.enable_history(2);
+    for x in 0..4 {
+        map.set(x, 0, true);
+    }
+
+    assert!(map.undo());
+    assert!(map.undo());
+    assert!(!map.undo());
+    assert_eq!(map.to_ascii(), "##..\n");
+}
+
+#[test]
+fn the_top_chunk_holds_overlapped_tiles() {
+    let mut map = map("....\n....\n");
+    map.insert_chunk(Chunk::from_flat(1, 0, 2, 2, vec![1; 4]));
+
+    // The last added chunk wins, until a higher z puts the first one back on top
+    assert!(map.get(1, 0) && map.get(2, 1) && !map.get(3, 0));
+    map.set(1, 0, false);
+    assert_eq!(map.chunks[1].data, vec![0, 1, 1, 1]);
+    assert!(map.set_chunk_z(0, 0, 1));
+    assert!(!map.get(2, 1));
+    map.set(2, 1, true);
+    assert_eq!(map.chunks[0].data, vec![0, 0, 0, 0, 0, 0, 1, 0]);
+}
+
+#[test]
+fn bulk_edits_keep_hidden_tiles_of_overlapped_chunks() {
+    let mut map = map("....\n....\n");
+    map.insert_chunk(Chunk::from_flat(0, 0, 2, 2, vec![0; 4]));
+    map.enable_change_tracking(true);
+
+    // The second chunk is on top, the first one's tiles under it stay untouched
+    map.fill_rect(0, 0, 4, 2, true);
+    assert_eq!(map.chunks[0].data, vec![0, 0, 1, 1, 0, 0, 1, 1]);
+    assert_eq!(map.chunks[1].data, vec![1; 4]);
+    assert_eq!(map.take_dirty().len(), 8);
+
+    map.invert();
+    assert_eq!(map.chunks[0].data, vec![0; 8]);
+    assert_eq!(map.take_dirty().len(), 8);
+
+    map.ca_step(0, 0);
+    assert_eq!(map.chunks[0].data, vec![0, 0, 1, 1, 0, 0, 1, 1]);
+    assert_eq!(map.chunks[1].data, vec![1; 4]);
+
+    map.take_dirty();
+    map.clear_chunk(0, 0);
+    assert_eq!(map.chunks[0].data, vec![0; 8]);
+    assert_eq!(map.chunks[1].data, vec![1; 4]);
+    assert_eq!(
+        map.take_dirty(),
+        HashSet::from([(2, 0), (3, 0), (2, 1), (3, 1)])
+    );
+}
+
+#[test]
+fn set_line_sets_every_tile_between_the_ends() {
+    let mut map = map("....\n....\n....\n....\n");
+    map.set_line(0, 0, 3, 3, true);
+    assert_eq!(map.to_ascii(), "#...\n.#..\n..#.\n...#\n");
+
+    map.fill_all(false);
+    map.set_line(3, 1, 0, 2, true);
+    assert_eq!(map.iter_active().count(), 4);
+    assert!(map.get(3, 1) && map.get(0, 2));
+}
+
+#[test]
+fn fill_all_and_invert_reach_every_chunk() {
+    let mut map = TileMap::new(rules()).unwrap();
+    map.add_chunk(0, 0, 3, 2);
+    map.add_chunk(3, 0, 2, 2);
+    map.set(1, 1, true);
+
+    map.fill_all(true);
+    assert_eq!(map.to_ascii(), "#####\n#####\n");
+    map.set(4, 0, false);
+    map.invert();
+    assert_eq!(map.to_ascii(), "....#\n.....\n");
+    map.fill_all(false);
+    assert_eq!(map.iter_active().count(), 0);
+}
+
+/// Source x of the sprite drawn for every full cell of a filled 10x10 map, in draw order
+fn full_cell_sprites(yaml: &str, seed: u64) -> Vec<f32> {
+    let rules = TileRules::new()
+        .with_bytes_yaml_file(yaml.as_bytes())
+        .unwrap()
+        .load_headless()
+        .unwrap();
+    let mut map = TileMap::new(rules).unwrap().with_seed(seed);
+    map.add_chunk(0, 0, 10, 10);
+    map.fill_all(true);
+
+    let mut renderer = RecordingRenderer::new();
+    map.draw_to(&mut renderer, Rectangle::new(0.0, 0.0, 640.0, 480.0));
+    renderer
+        .calls
+        .iter()
+        .filter(|call| call.neighbors == [1, 1, 1, 1])
+        .map(|call| call.sprite.source.x)
+        .collect()
+}
+
+#[test]
+fn variants_stay_put_for_a_cell() {
+    let yaml = "size: 8
+rules:
+  - neighbors: [true, true, true, true]
+    sprite: [{ x: 0, y: 0 }, { x: 8, y: 0, weight: 3.0 }, { x: 16, y: 0, weight: 0.0 }]
+";
+    let sprites = full_cell_sprites(yaml, 0);
+    assert_eq!(sprites.len(), 81);
+    assert_eq!(full_cell_sprites(yaml, 0), sprites);
+
+    // Both weighted variants show up, the one without weight never does
+    assert!(sprites.contains(&0.0) && sprites.contains(&8.0));
+    assert!(!sprites.contains(&16.0));
+    assert_ne!(full_cell_sprites(yaml, 1), sprites);
+}
+
+#[test]
+fn rules_built_in_code_need_no_rules_file() {
+    let mut rules = TileRules::new();
+    for i in 0..16u8 {
+        let rect = Rectangle::new((i % 4) as f32 * 8.0, (i / 4) as f32 * 8.0, 8.0, 8.0);
+        rules.add_rule(index_to_neighbors(i), rect);
+    }
+    let rules = rules.load_headless().unwrap();
+    assert!(rules.missing_combinations().is_empty());
+
+    let mut map = TileMap::new(rules).unwrap();
+    map.add_chunk(0, 0, 2, 2);
+    map.set(0, 0, true);
+    assert_eq!(
+        map.rule_at(0, 0),
+        Some(neighbors_to_index([true, false, false, false]) as usize)
+    );
+
+    // Without a rules file or any rule there's nothing to load
+    assert!(matches!(
+        TileRules::new().load_headless(),
+        Err(TileError::MissingYaml)
+    ));
+}
+
+#[test]
+fn maps_need_loaded_rules() {
+    assert!(matches!(
+        TileRules::new().check_loaded(),
+        Err(TileError::NotLoaded)
+    ));
+    assert!(matches!(
+        TileMap::new(TileRules::new()),
+        Err(TileError::NotLoaded)
+    ));
+    assert!(rules().check_loaded().is_ok());
+}
+
+#[test]
+fn iterators_walk_chunks_in_world_space() {
+    let mut map = map("#.\n.#\n");
+    map.add_chunk(-3, 5, 3, 1);
+    map.set(-1, 5, true);
+
+    assert_eq!(map.chunks().count(), 2);
+    assert_eq!(map.iter_tiles().count(), 7);
+    assert_eq!(
+        map.iter_active().collect::<Vec<_>>(),
+        vec![(0, 0), (1, 1), (-1, 5)]
+    );
+    assert!(map
+        .iter_tiles()
+        .all(|((x, y), filled)| map.get(x, y) == filled));
+}
+
+#[test]
+fn resizing_a_chunk_keeps_the_tiles_inside_both_sizes() {
+    let mut chunk = Chunk::from_flat(4, 4, 2, 2, vec![1, 0, 0, 1]);
+    chunk.set_tint(1, 1, Color::WHITE);
+
+    chunk.resize(3, 4);
+    assert_eq!((chunk.x, chunk.y, chunk.size_x, chunk.size_y), (4, 4, 3, 4));
+    assert_eq!(chunk.data, vec![1, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0]);
+
+    chunk.resize(1, 2);
+    assert_eq!(chunk.data, vec![1, 0]);
+    assert_eq!(chunk.get_tint(1, 1), None);
+
+    let mut map = map("##\n##\n");
+    assert!(map.resize_chunk(0, 0, 3, 1));
+    assert_eq!(map.to_ascii(), "##.\n");
+    assert!(!map.resize_chunk(1, 0, 3, 1));
+}
+
+#[test]
+fn merging_copies_the_other_map_at_the_offset() {
+    let room = map("#.\n.#\n");
+
+    let mut union = map("#...\n....\n...#\n");
+    union.merge(&room, 1, 1, false);
+    assert_eq!(union.to_ascii(), "#...\n.#..\n..##\n");
+
+    let mut replaced = map("####\n####\n####\n");
+    replaced.merge(&room, 1, 1, true);
+    assert_eq!(replaced.to_ascii(), "####\n##.#\n#.##\n");
+
+    // Tiles landing outside of every chunk are skipped, unless auto expand adds chunks
+    let mut clipped = map("..\n..\n");
+    clipped.merge(&room, 1, 1, false);
+    assert_eq!(clipped.to_ascii(), "..\n.#\n");
+    clipped.set_auto_expand(true);
+    clipped.merge(&room, 1, 1, false);
+    assert!(clipped.get(2, 2));
+}
+
+#[test]
+fn rotating_and_flipping_move_the_tiles() {
+    let mut map = map("....\n.#..\n.#..\n.##.\n");
+    map.rotate_90();
+    assert_eq!(map.to_ascii(), "###\n#..\n");
+    assert_eq!(map.extent(), Some((1, 1, 4, 3)));
+
+    map.flip_horizontal();
+    assert_eq!(map.to_ascii(), "###\n..#\n");
+    map.flip_vertical();
+    assert_eq!(map.to_ascii(), "..#\n###\n");
+}
+
+#[test]
+fn random_fill_is_seeded_and_follows_the_density() {
+    let filled = |density: f32, seed: u64| {
+        let mut map = TileMap::new(rules()).unwrap();
+        map.add_chunk(0, 0, 100, 100);
+        map.random_fill(density, seed);
+        map.to_ascii()
+    };
+
+    assert_eq!(filled(0.4, 7), filled(0.4, 7));
+    assert_ne!(filled(0.4, 7), filled(0.4, 8));
+
+    let count = filled(0.4, 7).chars().filter(|&c| c == '#').count();
+    assert!((3600..4400).contains(&count), "{} filled tiles", count);
+    assert!(!filled(0.0, 7).contains('#'));
+    assert!(!filled(1.0, 7).contains('.'));
+}
+
+#[test]
+fn shift_moves_the_tiles_and_not_the_chunks() {
+    let mut map = map("##..\n#...\n....\n");
+    map.shift(1, 1);
+    assert_eq!(map.to_ascii(), "....\n.##.\n.#..\n");
+    assert_eq!(map.extent(), Some((0, 0, 4, 3)));
+
+    // Tiles moved off the chunk are lost, unless the edge mode wraps them around
+    map.shift(2, 0);
+    assert_eq!(map.to_ascii(), "....\n...#\n...#\n");
+    map.set_edge_mode(EdgeMode::Wrap);
+    map.shift(1, -1);
+    assert_eq!(map.to_ascii(), "#...\n#...\n....\n");
+}
+
+#[test]
+fn screen_positions_reach_the_tile_under_them() {
+    let mut map = map("....\n....\n");
+    assert_eq!(map.scale(), 4.0);
+
+    // A tile is 8 pixels at scale 4, so 32 on screen
+    map.set_at_screen(Vector2::new(70.0, 33.0), true);
+    assert!(map.get(2, 1));
+    assert!(map.get_at_screen(Vector2::new(64.0, 63.9)));
+    assert!(!map.get_at_screen(Vector2::new(63.9, 63.9)));
+    assert!(!map.get_at_screen(Vector2::new(96.0, 32.0)));
+}
+
+#[test]
+fn chunks_can_start_out_filled() {
+    let mut map = TileMap::new(rules()).unwrap();
+    map.add_chunk_with_data(
+        2,
+        -1,
+        vec![vec![true, false, true], vec![false, true, false]],
+    )
+    .unwrap();
+    assert_eq!(map.extent(), Some((2, -1, 5, 1)));
+    assert_eq!(
+        map.iter_active().collect::<Vec<_>>(),
+        vec![(2, -1), (4, -1), (3, 0)]
+    );
+
+    assert!(matches!(
+        map.add_chunk_with_data(0, 4, vec![vec![true, false], vec![true]]),
+        Err(TileError::RaggedChunk)
+    ));
+    assert_eq!(map.chunks().count(), 1);
+}
+
+#[test]
+fn take_dirty_returns_the_tiles_changed_since_the_last_call() {
+    let mut map = map("....\n....\n");
+    map.set(0, 0, true);
+    assert!(map.take_dirty().is_empty());
+
+    map.enable_change_tracking(true);
+    map.set(1, 0, true);
+    map.set(0, 0, true);
+    map.fill_rect(2, 1, 2, 1, true);
+    map.flood_fill(3, 0, true);
+    assert_eq!(
+        map.take_dirty(),
+        HashSet::from([(1, 0), (2, 1), (3, 1), (2, 0), (3, 0)])
+    );
+    assert!(map.take_dirty().is_empty());
+
+    map.enable_history(1);
+    map.set(0, 0, false);
+    map.undo();
+    assert_eq!(map.take_dirty(), HashSet::from([(0, 0)]));
+}
+
+#[test]
+fn on_change_fires_once_per_changed_tile() {
+    let calls = Rc::new(RefCell::new(Vec::new()));
+    let mut map = map("#...\n....\n");
+    let log = calls.clone();
+    map.on_change(Box::new(move |x, y, solid| {
+        log.borrow_mut().push((x, y, solid))
+    }));
+
+    map.set(0, 0, true);
+    map.set(1, 0, true);
+    map.fill_rect(0, 1, 2, 1, true);
+    assert_eq!(
+        *calls.borrow(),
+        vec![(1, 0, true), (0, 1, true), (1, 1, true)]
+    );
+
+    map.invert();
+    assert_eq!(calls.borrow().len(), 3 + 8);
+
+    map.clear_on_change();
+    map.set(3, 1, true);
+    assert_eq!(calls.borrow().len(), 11);
+}
+
+#[test]
+fn duplicate_rules_fail_only_when_strict() {
+    let yaml = "size: 8
+rules:
+  - neighbors: [true, true, true, true]
+    sprite: { x: 0, y: 0 }
+  - neighbors: [true, true, true, true]
+    sprite: { x: 8, y: 0 }
+";
+    let rules = || {
+        TileRules::new()
+            .with_bytes_yaml_file(yaml.as_bytes())
+            .unwrap()
+    };
+    assert!(matches!(
+        rules().with_strict(true).load_headless(),
+        Err(TileError::DuplicateRule(0, 1))
+    ));
+
+    // Without strict mode only the first of them is drawn
+    assert!(rules().load_headless().is_ok());
+    let sprites = full_cell_sprites(yaml, 0);
+    assert!(!sprites.is_empty());
+    assert!(sprites.iter().all(|&x| x == 0.0));
+}
+
+#[test]
+fn wrapping_maps_read_write_and_draw_across_the_seam() {
+    let mut arena = map("....\n....\n");
+    arena.set_wrap(Some((4, 0)));
+    assert_eq!(arena.wrap(), None);
+
+    arena.set_wrap(Some((4, 2)));
+    assert_eq!(arena.wrap(), Some((4, 2)));
+    arena.set(4, -2, true);
+    arena.set(-4, 3, true);
+    assert!(arena.get(0, 0) && arena.get(0, 1));
+    assert!(arena.get(-4, 0) && !arena.get(-1, 0));
+    assert!(arena[(-4, 0)] && !arena[(-1, 0)] && arena[(8, 5)]);
+
+    // The cell after the last column sees the first one
+    let seam = drawn_at(&arena, 112.0, 16.0);
+    assert!(!seam.is_empty());
+    assert!(seam.iter().all(|&corners| corners == [0, 1, 0, 1]));
+}
+
+#[test]
+fn hidden_chunks_are_not_drawn() {
+    let mut map = TileMap::new(rules()).unwrap();
+    map.add_chunk(0, 0, 2, 2);
+    map.add_chunk(2, 0, 2, 2);
+    map.fill_all(true);
+    map.clear_dirty();
+
+    assert!(!map.set_chunk_visible(1, 0, false));
+    assert!(map.set_chunk_visible(2, 0, false));
+    assert!(map.is_dirty());
+    assert!(map.get(3, 1));
+
+    let mut renderer = RecordingRenderer::new();
+    map.draw_to(&mut renderer, Rectangle::new(0.0, 0.0, 640.0, 480.0));
+    assert!(!renderer.calls.is_empty());
+    assert!(renderer.calls.iter().all(|call| call.dest.x < 64.0));
+
+    map.clear_dirty();
+    map.set_tint(0, 0, Color::new(255, 0, 0, 255));
+    assert!(map.is_dirty());
+    map.clear_dirty();
+    map.clear_tint(0, 0);
+    assert!(map.is_dirty());
+}
+
+#[test]
+fn memory_stats_sum_every_chunk() {
+    let mut map = TileMap::new(rules()).unwrap();
+    assert_eq!(map.memory_stats(), MemoryStats::default());
+
+    map.add_chunk(0, 0, 4, 2);
+    map.add_chunk(2, 0, 3, 3);
+    let stats = map.memory_stats();
+    assert_eq!(stats.chunks, 2);
+    assert_eq!(stats.cells, 8 + 9);
+    assert!(stats.data_bytes >= stats.cells);
+    assert_eq!(stats.textures, 0);
+}
+
+#[test]
+fn drawing_at_an_origin_moves_every_tile() {
+    let map = map("#..\n.##\n");
+    let view = Rectangle::new(-100.0, -50.0, 640.0, 480.0);
+    let mut placed = RecordingRenderer::new();
+    map.draw_scaled(&mut placed, view, map.scale(), Vector2::new(100.0, 50.0));
+    let mut unmoved = RecordingRenderer::new();
+    map.draw_to(&mut unmoved, view);
+
+    assert!(!placed.calls.is_empty());
+    assert_eq!(placed.calls.len(), unmoved.calls.len());
+    for (placed, unmoved) in placed.calls.iter().zip(unmoved.calls.iter()) {
+        assert_eq!(placed.dest.x, unmoved.dest.x + 100.0);
+        assert_eq!(placed.dest.y, unmoved.dest.y + 50.0);
+        assert_eq!(placed.neighbors, unmoved.neighbors);
+    }
+}
+
+#[test]
+fn transitions_draw_between_named_materials() {
+    let yaml = "size: 8
+materials: { grass: 1, sand: 2 }
+transitions:
+  - corners: [grass, grass, sand, sand]
+    sprite: { x: 0, y: 0 }
+  - corners: [sand, sand, empty, grass]
+    sprite: { x: 8, y: 0 }
+";
+    let rules = TileRules::new()
+        .load_transitions(yaml)
+        .unwrap()
+        .load_headless()
+        .unwrap();
+    let mut map = TileMap::new(rules).unwrap();
+    map.add_chunk(0, 0, 2, 3);
+    map.set_tile(0, 0, 1);
+    map.set_tile(1, 0, 1);
+    map.set_tile(0, 1, 2);
+    map.set_tile(1, 1, 2);
+    map.set_tile(1, 2, 1);
+
+    let mut renderer = RecordingRenderer::new();
+    map.draw_to(&mut renderer, Rectangle::new(0.0, 0.0, 640.0, 480.0));
+    let sprite_at = |x: f32, y: f32| {
+        renderer
+            .calls
+            .iter()
+            .find(|call| call.dest.x == x && call.dest.y == y)
+            .map(|call| call.sprite.source.x)
+    };
+    assert_eq!(sprite_at(16.0, 16.0), Some(0.0));
+    assert_eq!(sprite_at(16.0, 48.0), Some(8.0));
+
+    let unknown = yaml.replace("[sand, sand, empty", "[sand, stone, empty");
+    assert!(matches!(
+        TileRules::new().load_transitions(&unknown),
+        Err(TileError::UnknownMaterial(name)) if name == "stone"
+    ));
+}
+
+#[test]
+fn rule_at_names_the_combination_drawn() {
+    let diagonal = map("#.\n.#\n");
+    assert_eq!(
+        diagonal.rule_at(0, 0),
+        Some(neighbors_to_index([true, false, false, true]) as usize)
+    );
+    assert_eq!(
+        diagonal.rule_at(-1, -1),
+        Some(neighbors_to_index([false, false, false, true]) as usize)
+    );
+    assert_eq!(diagonal.rule_at(2, 0), None);
+
+    // A combination the rules don't cover is never drawn
+    let rules = TileRules::new()
+        .with_bytes_yaml_file(b"size: 8\nrules:\n  - neighbors: [true, true, true, true]\n    sprite: { x: 0, y: 0 }\n")
+        .unwrap()
+        .with_completeness_check(false)
+        .load_headless()
+        .unwrap();
+    let mut full = TileMap::new(rules).unwrap();
+    full.add_chunk(0, 0, 2, 2);
+    full.fill_all(true);
+    assert_eq!(full.rule_at(0, 0), Some(15));
+    assert_eq!(full.rule_at(1, 0), None);
+}
+
+#[test]
+fn paint_world_sets_a_disc_under_the_position() {
+    let mut canvas = map(".....\n.....\n.....\n.....\n");
+    canvas.enable_history(4);
+    canvas.paint_world(Vector2::new(2.0 * 32.0 + 5.0, 1.0 * 32.0 + 30.0), true, 1);
+    assert_eq!(canvas.to_ascii(), "..#..\n.###.\n..#..\n.....\n");
+
+    // One undo takes back the whole stroke
+    canvas.undo();
+    assert_eq!(canvas.to_ascii(), ".....\n.....\n.....\n.....\n");
+
+    canvas.paint_world(Vector2::new(0.0, 127.0), true, 0);
+    assert_eq!(canvas.to_ascii(), ".....\n.....\n.....\n#....\n");
+}
+
+#[test]
+fn rules_files_have_to_be_utf8() {
+    let bytes = b"size: 8\xff\n";
+    #[allow(unused_mut)]
+    let mut results = vec![
+        TileRules::new().with_bytes_yaml_file(bytes).err(),
+        TileRules::new().with_bytes_json_file(bytes).err(),
+        TileRules::new().with_bytes_transitions_file(bytes).err(),
+    ];
+    #[cfg(feature = "ron")]
+    results.push(TileRules::new().with_bytes_ron_file(bytes).err());
+    for result in results {
+        let Some(error @ TileError::InvalidUtf8(_)) = result else {
+            panic!("expected an invalid utf-8 error, got {:?}", result);
+        };
+        assert!(error
+            .to_string()
+            .starts_with("the rules file is not valid utf-8"));
+        assert!(std::error::Error::source(&error).is_some());
+    }
+}
+
+#[test]
+fn neighbor_indices_round_trip() {
+    for index in 0..16u8 {
+        assert_eq!(neighbors_to_index(index_to_neighbors(index)), index);
+    }
+    assert_eq!(index_to_neighbors(16 + 2), index_to_neighbors(2));
+
+    // The lowest bit is the first of the corners
+    for (i, &(dx, dy)) in CORNERS.iter().enumerate() {
+        let mut corner = map("..\n..\n");
+        corner.set(dx, dy, true);
+        assert_eq!(corner.rule_at(0, 0), Some(1 << i));
+    }
+}
+
+#[test]
+fn densities_fill_tiles_past_the_threshold() {
+    let mut terrain = map("....\n");
+    terrain.set_density(0, 0, 200);
+    terrain.set_density(1, 0, 100);
+    terrain.set_tile(2, 0, 3);
+    terrain.set_density(2, 0, 130);
+    assert_eq!(terrain.to_ascii(), "#.#.\n");
+    assert_eq!(terrain.get_tile(2, 0), 3);
+    assert_eq!(terrain.get_density(0, 0), 200);
+    assert_eq!(terrain.get_density(1, 0), 100);
+    assert_eq!(terrain.get_density(3, 0), 0);
+
+    terrain.set_density_threshold(140);
+    assert_eq!(terrain.to_ascii(), "#...\n");
+    terrain.set_density_threshold(90);
+    assert_eq!(terrain.to_ascii(), "###.\n");
+
+    // Any other edit drops the density, so later thresholds leave the tile alone
+    terrain.set(0, 0, false);
+    terrain.fill_rect(1, 0, 1, 1, false);
+    terrain.set_density_threshold(10);
+    assert_eq!(terrain.to_ascii(), "..#.\n");
+    assert_eq!(terrain.get_density(0, 0), 0);
+    assert_eq!(terrain.get_density(2, 0), 130);
+
+    assert!(terrain.clear_chunk(0, 0));
+    terrain.set_density_threshold(0);
+    assert_eq!(terrain.to_ascii(), "....\n");
+}
+
+#[test]
+fn clear_chunk_empties_the_chunk_in_place() {
+    let mut room = TileMap::new(rules()).unwrap();
+    room.add_chunk(0, 0, 2, 2);
+    room.add_chunk(2, 0, 2, 2);
+    room.fill_all(true);
+    room.enable_history(4);
+
+    assert!(!room.clear_chunk(1, 0));
+    assert!(room.clear_chunk(2, 0));
+    assert_eq!(room.chunks().count(), 2);
+    assert_eq!(room.to_ascii(), "##..\n##..\n");
+
+    room.undo();
+    assert_eq!(room.to_ascii(), "####\n####\n");
+}
+
+#[test]
+fn minimaps_draw_a_rectangle_per_filled_tile() {
+    let mut minimap = map("....\n.#..\n.##.\n");
+    let solid = Color::new(255, 255, 255, 255);
+    let empty = Color::new(0, 0, 0, 255);
+    let mut renderer = RecordingRenderer::new();
+    minimap.draw_minimap_to(
+        &mut renderer,
+        Rectangle::new(0.0, 0.0, 40.0, 20.0),
+        solid,
+        empty,
+    );
+
+    // The background, then the 3 filled tiles of the 2x2 active bounds fitted into 20x20
+    assert_eq!(renderer.rects.len(), 1 + 3);
+    assert_eq!(
+        renderer.rects[0],
+        (Rectangle::new(10.0, 0.0, 20.0, 20.0), empty)
+    );
+    assert!(renderer.rects[1..].iter().all(|&(_, color)| color == solid));
+    assert!(renderer
+        .rects
+        .contains(&(Rectangle::new(20.0, 10.0, 10.0, 10.0), solid)));
+    assert!(renderer.calls.is_empty());
+
+    minimap.fill_all(false);
+    let mut renderer = RecordingRenderer::new();
+    minimap.draw_minimap_to(
+        &mut renderer,
+        Rectangle::new(0.0, 0.0, 40.0, 20.0),
+        solid,
+        empty,
+    );
+    assert!(renderer.rects.is_empty());
+}
+
+#[test]
+fn batching_reorders_the_same_draw_calls() {
+    let mut cave = map("#..#\n.##.\n##.#\n...#\n");
+    let view = Rectangle::new(0.0, 0.0, 640.0, 480.0);
+    let key = |call: &DrawCall| (call.sprite.source.x as i32, call.sprite.source.y as i32);
+    let position = |call: &DrawCall| (call.dest.x as i32, call.dest.y as i32);
+
+    let mut unbatched = RecordingRenderer::new();
+    cave.draw_to(&mut unbatched, view);
+    cave.set_batching(true);
+    let mut batched = RecordingRenderer::new();
+    cave.draw_to(&mut batched, view);
+    assert_ne!(batched.calls, unbatched.calls);
+
+    // Batched calls come sorted by sprite, every cell still drawn once the same way
+    assert!(batched.calls.windows(2).all(|w| key(&w[0]) <= key(&w[1])));
+    let sorted = |calls: &[DrawCall]| {
+        let mut calls = calls.to_vec();
+        calls.sort_by_key(|call| (key(call), position(call)));
+        calls
+    };
+    assert_eq!(sorted(&batched.calls), sorted(&unbatched.calls));
+}
+
+#[test]
+fn densities_survive_cropping_and_rotating() {
+    let mut terrain = map("....\n....\n....\n");
+    terrain.set_density(0, 0, 200);
+    terrain.set_density(2, 2, 180);
+    terrain.set_density(1, 1, 100);
+    terrain.set_density(3, 0, 90);
+
+    // Densities outside of the active bounds are dropped along with their tiles
+    terrain.crop_to_active();
+    assert_eq!(terrain.get_density(1, 1), 100);
+    assert_eq!(terrain.get_density(3, 0), 0);
+    terrain.rotate_90();
+    assert_eq!(terrain.to_ascii(), "..#\n...\n#..\n");
+    assert_eq!(terrain.get_density(2, 0), 200);
+
+    // The tile left empty below the threshold fills once it drops
+    terrain.set_density_threshold(95);
+    assert_eq!(terrain.to_ascii(), "..#\n.#.\n#..\n");
+    assert_eq!(terrain.get_density(1, 1), 100);
+}